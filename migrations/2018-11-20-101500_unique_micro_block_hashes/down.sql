@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS micro_blocks_hash;
+CREATE INDEX micro_blocks_hash ON micro_blocks(hash);
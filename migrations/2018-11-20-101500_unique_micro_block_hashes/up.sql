@@ -0,0 +1,14 @@
+-- Micro blocks are upserted by hash when a generation is (re)loaded,
+-- so the hash has to be unique. Drop any duplicates left behind by
+-- earlier partial loads first, keeping the latest copy of each. Their
+-- transactions are moved onto it, as deleting cascades to them.
+
+UPDATE transactions t SET micro_block_id = k.id
+FROM micro_blocks a, (SELECT hash, MAX(id) AS id FROM micro_blocks GROUP BY hash) k
+WHERE t.micro_block_id = a.id AND a.hash = k.hash AND a.id < k.id;
+
+DELETE FROM micro_blocks a USING micro_blocks b
+       WHERE a.hash = b.hash AND a.id < b.id;
+
+DROP INDEX IF EXISTS micro_blocks_hash;
+CREATE UNIQUE INDEX micro_blocks_hash ON micro_blocks(hash);
@@ -0,0 +1,7 @@
+CREATE TABLE load_queue (
+       height BIGINT PRIMARY KEY,
+       reason VARCHAR(32) NOT NULL,
+       attempts INT NOT NULL DEFAULT 0,
+       last_error TEXT NULL,
+       enqueued_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS orphaned_transactions;
+DROP TABLE IF EXISTS orphaned_micro_blocks;
+DROP TABLE IF EXISTS orphaned_key_blocks;
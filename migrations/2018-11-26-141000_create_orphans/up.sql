@@ -0,0 +1,56 @@
+-- Blocks and transactions which were on the losing side of a fork.
+-- They are copied here just before being removed from the main
+-- tables, and are linked by hash rather than id since the originals
+-- are gone.
+
+CREATE TABLE orphaned_key_blocks (
+       id SERIAL PRIMARY KEY,
+       hash VARCHAR(55) NOT NULL,
+       height BIGINT NOT NULL,
+       miner VARCHAR(55),
+       beneficiary VARCHAR(55),
+       nonce numeric(20,0),
+       pow TEXT,
+       prev_hash VARCHAR(55),
+       prev_key_hash VARCHAR(55),
+       state_hash VARCHAR(55),
+       target BIGINT,
+       time_ BIGINT,
+       version INTEGER,
+       orphaned_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX orphaned_key_blocks_height ON orphaned_key_blocks(height);
+
+CREATE TABLE orphaned_micro_blocks (
+       id SERIAL PRIMARY KEY,
+       key_block_hash VARCHAR(55) NOT NULL,
+       height BIGINT NOT NULL,
+       hash VARCHAR(55) NOT NULL,
+       pof_hash VARCHAR(55) NOT NULL,
+       prev_hash VARCHAR(55) NOT NULL,
+       prev_key_hash VARCHAR(55) NOT NULL,
+       signature VARCHAR(255) NOT NULL,
+       state_hash VARCHAR(255) NOT NULL,
+       txs_hash VARCHAR(255) NOT NULL,
+       version INT NOT NULL,
+       orphaned_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX orphaned_micro_blocks_hash ON orphaned_micro_blocks(hash);
+
+CREATE TABLE orphaned_transactions (
+       id SERIAL PRIMARY KEY,
+       micro_block_hash VARCHAR(55) NOT NULL,
+       key_block_hash VARCHAR(55) NOT NULL,
+       block_height INT NOT NULL,
+       hash VARCHAR(55) NOT NULL,
+       signatures TEXT NOT NULL,
+       tx_type VARCHAR(64) NOT NULL,
+       tx JSONB NOT NULL,
+       fee BIGINT NOT NULL,
+       size INT NOT NULL,
+       orphaned_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX orphaned_transactions_hash ON orphaned_transactions(hash);
@@ -0,0 +1,11 @@
+CREATE TABLE reorgs (
+       id SERIAL PRIMARY KEY,
+       detected_at TIMESTAMP NOT NULL DEFAULT NOW(),
+       from_height BIGINT NOT NULL,
+       to_height BIGINT NOT NULL,
+       old_hashes VARCHAR(55)[] NOT NULL,
+       new_hashes VARCHAR(55)[] NOT NULL,
+       transactions_affected INT NOT NULL
+);
+
+CREATE INDEX reorgs_detected_at ON reorgs(detected_at);
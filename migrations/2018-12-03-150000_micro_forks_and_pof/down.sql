@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS proofs_of_fraud;
+ALTER TABLE reorgs DROP COLUMN kind;
@@ -0,0 +1,15 @@
+-- 'key' when key blocks were replaced, 'micro' when micro blocks
+-- within a generation were.
+ALTER TABLE reorgs ADD COLUMN kind VARCHAR(16) NOT NULL DEFAULT 'key';
+
+CREATE TABLE proofs_of_fraud (
+       id SERIAL PRIMARY KEY,
+       micro_block_hash VARCHAR(55) UNIQUE NOT NULL,
+       pof_hash VARCHAR(55) NOT NULL,
+       height BIGINT NOT NULL,
+       reporter VARCHAR(55) NOT NULL,
+       offender VARCHAR(55) NULL,
+       detected_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX proofs_of_fraud_offender ON proofs_of_fraud(offender);
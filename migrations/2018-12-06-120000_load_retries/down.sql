@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS dead_letters;
+ALTER TABLE load_queue DROP COLUMN next_attempt_at;
@@ -0,0 +1,11 @@
+ALTER TABLE load_queue ADD COLUMN next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW();
+
+-- Heights which failed to load too many times to keep retrying.
+CREATE TABLE dead_letters (
+       height BIGINT PRIMARY KEY,
+       reason VARCHAR(32) NOT NULL,
+       attempts INT NOT NULL,
+       last_error TEXT NULL,
+       raw_response TEXT NULL,
+       failed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,12 @@
+-- What happened to each transaction, and when: seen_in_mempool,
+-- mined, dropped, reorged_out or re_mined.
+CREATE TABLE transaction_events (
+       id SERIAL PRIMARY KEY,
+       transaction_hash VARCHAR(55) NOT NULL,
+       event VARCHAR(16) NOT NULL,
+       block_height INT NULL,
+       micro_block_hash VARCHAR(55) NULL,
+       happened_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX transaction_events_hash ON transaction_events(transaction_hash);
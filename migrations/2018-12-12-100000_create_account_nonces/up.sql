@@ -0,0 +1,20 @@
+-- The highest nonce each account has used in a mined transaction.
+CREATE TABLE account_nonces (
+       account VARCHAR(55) PRIMARY KEY,
+       nonce BIGINT NOT NULL,
+       updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- The account whose nonce a transaction uses has a different name
+-- depending on the transaction's type. Oracle transactions use the
+-- nonce of the account which registered the oracle, which has the
+-- same key.
+INSERT INTO account_nonces (account, nonce)
+SELECT sender, MAX(nonce) FROM (
+       SELECT COALESCE(tx->>'sender_id', tx->>'account_id', tx->>'owner_id',
+                       tx->>'caller_id', tx->>'initiator_id', tx->>'from_id',
+                       'ak_' || substr(tx->>'oracle_id', 4), tx->>'to_id') AS sender,
+              (tx->>'nonce')::BIGINT AS nonce
+       FROM transactions WHERE micro_block_id IS NOT NULL) s
+WHERE sender IS NOT NULL AND nonce IS NOT NULL
+GROUP BY sender;
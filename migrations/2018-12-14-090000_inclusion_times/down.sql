@@ -0,0 +1,3 @@
+ALTER TABLE transactions DROP COLUMN mined_at;
+ALTER TABLE transactions DROP COLUMN first_seen;
+ALTER TABLE micro_blocks DROP COLUMN time_;
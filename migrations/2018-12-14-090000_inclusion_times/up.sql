@@ -0,0 +1,21 @@
+-- When the micro block was made, in milliseconds like key_blocks.time_.
+ALTER TABLE micro_blocks ADD COLUMN time_ BIGINT NOT NULL DEFAULT 0;
+
+-- When the mempool poller first saw a transaction, and the time of the
+-- micro block it was mined in.
+ALTER TABLE transactions ADD COLUMN first_seen TIMESTAMP NULL;
+ALTER TABLE transactions ADD COLUMN mined_at TIMESTAMP NULL;
+
+UPDATE transactions t SET first_seen = e.first_seen
+FROM (SELECT transaction_hash, MIN(happened_at) AS first_seen
+      FROM transaction_events WHERE event = 'seen_in_mempool'
+      GROUP BY transaction_hash) e
+WHERE e.transaction_hash = t.hash;
+
+UPDATE transactions t SET mined_at = e.mined_at
+FROM (SELECT transaction_hash, MAX(happened_at) AS mined_at
+      FROM transaction_events WHERE event IN ('mined', 're_mined')
+      GROUP BY transaction_hash) e
+WHERE e.transaction_hash = t.hash AND t.micro_block_id IS NOT NULL;
+
+CREATE INDEX transactions_mined_at ON transactions(mined_at);
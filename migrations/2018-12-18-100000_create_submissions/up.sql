@@ -0,0 +1,15 @@
+-- Transactions posted to the node through the middleware, who posted
+-- them, and what has happened to them since: 'pending', 'mined' or
+-- 'dropped'.
+CREATE TABLE submissions (
+       id SERIAL PRIMARY KEY,
+       hash VARCHAR(55) UNIQUE NOT NULL,
+       remote_addr VARCHAR(64) NULL,
+       forwarded_for VARCHAR(255) NULL,
+       user_agent VARCHAR(255) NULL,
+       submitted_at TIMESTAMP NOT NULL DEFAULT NOW(),
+       status VARCHAR(16) NOT NULL DEFAULT 'pending',
+       block_height INT NULL,
+       micro_block_hash VARCHAR(55) NULL,
+       updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,40 @@
+-- Every account, contract, oracle, name and channel a transaction
+-- involves, and its role in the transaction, which is the name of the
+-- field it is in without the _id, e.g. 'sender' or 'recipient'.
+CREATE TABLE transaction_participants (
+       id SERIAL PRIMARY KEY,
+       transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+       account_id VARCHAR(55) NOT NULL,
+       role VARCHAR(32) NOT NULL,
+       UNIQUE (transaction_id, account_id, role)
+);
+
+CREATE INDEX transaction_participants_account ON transaction_participants(account_id, role);
+
+-- ids in fields of their own
+INSERT INTO transaction_participants (transaction_id, account_id, role)
+SELECT t.id, f.value #>> '{}', regexp_replace(f.key, '_id$', '')
+FROM transactions t, jsonb_each(t.tx) f
+WHERE jsonb_typeof(f.value) = 'string'
+AND substr(f.value #>> '{}', 1, 3) IN ('ak_', 'ct_', 'ok_', 'nm_', 'ch_')
+ON CONFLICT DO NOTHING;
+
+-- lists of ids, e.g. delegate_ids
+INSERT INTO transaction_participants (transaction_id, account_id, role)
+SELECT t.id, e.value #>> '{}', regexp_replace(f.key, '_ids$', '')
+FROM transactions t, jsonb_each(t.tx) f,
+     jsonb_array_elements(CASE WHEN jsonb_typeof(f.value) = 'array'
+                          THEN f.value ELSE '[]'::JSONB END) e
+WHERE jsonb_typeof(e.value) = 'string'
+AND substr(e.value #>> '{}', 1, 3) IN ('ak_', 'ct_', 'ok_', 'nm_', 'ch_')
+ON CONFLICT DO NOTHING;
+
+-- what names point to
+INSERT INTO transaction_participants (transaction_id, account_id, role)
+SELECT t.id, e.value->>'id', 'pointer'
+FROM transactions t, jsonb_each(t.tx) f,
+     jsonb_array_elements(CASE WHEN jsonb_typeof(f.value) = 'array'
+                          THEN f.value ELSE '[]'::JSONB END) e
+WHERE jsonb_typeof(e.value) = 'object'
+AND substr(e.value->>'id', 1, 3) IN ('ak_', 'ct_', 'ok_', 'nm_', 'ch_')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS transactions_tx_type;
+DROP INDEX IF EXISTS transactions_position;
+ALTER TABLE transactions DROP COLUMN transaction_index;
+ALTER TABLE transactions DROP COLUMN micro_block_index;
@@ -0,0 +1,21 @@
+-- Where a mined transaction is in its generation: the position of its
+-- micro block in the generation, and its own position in the micro
+-- block, both from 0. -1 while it is in the mempool, like block_height,
+-- so that (block_height, micro_block_index, transaction_index, id)
+-- orders every transaction.
+ALTER TABLE transactions ADD COLUMN micro_block_index INT NOT NULL DEFAULT -1;
+ALTER TABLE transactions ADD COLUMN transaction_index INT NOT NULL DEFAULT -1;
+
+-- Blocks and their transactions are stored in chain order, so ids
+-- give the positions of what is already here.
+UPDATE transactions t SET micro_block_index = p.micro_block_index,
+                          transaction_index = p.transaction_index
+FROM (SELECT t.id,
+             DENSE_RANK() OVER (PARTITION BY m.key_block_id ORDER BY m.id) - 1 AS micro_block_index,
+             ROW_NUMBER() OVER (PARTITION BY t.micro_block_id ORDER BY t.id) - 1 AS transaction_index
+      FROM transactions t, micro_blocks m
+      WHERE t.micro_block_id = m.id) p
+WHERE p.id = t.id;
+
+CREATE INDEX transactions_position ON transactions(block_height, micro_block_index, transaction_index, id);
+CREATE INDEX transactions_tx_type ON transactions(tx_type);
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS key_blocks_time;
@@ -0,0 +1,2 @@
+-- For finding the key block at a time, and the generations between two.
+CREATE INDEX key_blocks_time ON key_blocks(time_);
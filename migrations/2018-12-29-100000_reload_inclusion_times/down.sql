@@ -0,0 +1,2 @@
+-- The cleared times can't be put back, nor are they worth having.
+SELECT 1;
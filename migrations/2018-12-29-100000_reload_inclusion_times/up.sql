@@ -0,0 +1,17 @@
+-- mined_at was filled in from when the transaction events were
+-- recorded, which is when we loaded a transaction, not when it was
+-- mined. Take it from the micro block instead, where we know its
+-- time, and reload every generation with a micro block we don't know
+-- the time of, which sets the micro block times and mined_at from the
+-- node.
+UPDATE transactions t
+SET mined_at = CASE WHEN m.time_ = 0 THEN NULL
+                    ELSE to_timestamp(m.time_ / 1000.0) AT TIME ZONE 'UTC' END
+FROM micro_blocks m
+WHERE m.id = t.micro_block_id;
+
+INSERT INTO load_queue (height, reason)
+SELECT DISTINCT k.height, 'refresh' FROM key_blocks k
+JOIN micro_blocks m ON m.key_block_id = k.id
+WHERE m.time_ = 0
+ON CONFLICT (height) DO NOTHING;
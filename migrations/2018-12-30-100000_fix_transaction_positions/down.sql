@@ -0,0 +1,2 @@
+-- The old positions can't be put back, nor are they worth having.
+CREATE INDEX transactions_tx_type ON transactions(tx_type);
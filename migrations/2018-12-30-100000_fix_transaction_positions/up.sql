@@ -0,0 +1,34 @@
+-- transactions_tx_type_index already covers tx_type.
+DROP INDEX IF EXISTS transactions_tx_type;
+
+-- The positions were backfilled in the order of micro block ids, which
+-- isn't the order of the chain when a generation was loaded more than
+-- once. Micro blocks are ordered by following prev_hash from the key
+-- block they belong to instead. Transactions loaded with their micro
+-- block were stored in order, so their ids give their positions in it.
+WITH RECURSIVE chain AS (
+    SELECT m.id, m.key_block_id, m.hash, 0 AS micro_block_index
+    FROM micro_blocks m, key_blocks k
+    WHERE m.key_block_id = k.id AND m.prev_hash = k.hash
+    UNION ALL
+    SELECT m.id, m.key_block_id, m.hash, c.micro_block_index + 1
+    FROM micro_blocks m, chain c
+    WHERE m.key_block_id = c.key_block_id AND m.prev_hash = c.hash
+)
+UPDATE transactions t SET micro_block_index = p.micro_block_index,
+                          transaction_index = p.transaction_index
+FROM (SELECT t.id, c.micro_block_index,
+             ROW_NUMBER() OVER (PARTITION BY t.micro_block_id ORDER BY t.id) - 1 AS transaction_index
+      FROM transactions t, chain c
+      WHERE t.micro_block_id = c.id) p
+WHERE p.id = t.id;
+
+-- Transactions seen in the mempool first were stored before the rest
+-- of their micro block, so reload the generations where that may have
+-- put them out of order.
+INSERT INTO load_queue (height, reason)
+SELECT DISTINCT t.block_height, 'refresh' FROM transactions t
+WHERE t.first_seen IS NOT NULL
+AND t.micro_block_id IN (SELECT micro_block_id FROM transactions
+                         GROUP BY micro_block_id HAVING COUNT(*) > 1)
+ON CONFLICT (height) DO NOTHING;
@@ -0,0 +1,154 @@
+use base64;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use rust_base58::{FromBase58, ToBase58};
+use std;
+
+/*
+ * The node's API gives binary values as a two letter prefix saying
+ * what they are, an underscore, and the value with a 4 byte checksum
+ * (the start of its double SHA-256) appended, in base58 for hashes and
+ * keys and base64 for everything else.
+ */
+const BASE58_PREFIXES: [&str; 13] =
+    ["ak", "ok", "ct", "nm", "ch", "cm", "kh", "mh", "bf", "th", "sg", "oq", "st"];
+const BASE64_PREFIXES: [&str; 5] = ["tx", "ba", "cb", "pi", "ss"];
+
+pub fn is_known_prefix(prefix: &str) -> bool {
+    BASE58_PREFIXES.contains(&prefix) || BASE64_PREFIXES.contains(&prefix)
+}
+
+fn checksum(data: &[u8]) -> Vec<u8> {
+    let mut once = [0u8; 32];
+    let mut twice = [0u8; 32];
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result(&mut once);
+    hasher.reset();
+    hasher.input(&once);
+    hasher.result(&mut twice);
+    twice[0..4].to_vec()
+}
+
+/*
+ * Split "pp_..." into its prefix and value, checking the checksum.
+ */
+pub fn decode(encoded: &str) -> Result<(String, Vec<u8>), Box<std::error::Error>> {
+    // get() rather than slicing, which panics inside a multibyte character
+    let prefix = match (encoded.get(0..2), encoded.get(2..3)) {
+        (Some(x), Some("_")) => x,
+        _ => return Err(From::from(format!("{} has no prefix", encoded))),
+    };
+    let data = if BASE58_PREFIXES.contains(&prefix) {
+        match encoded[3..].from_base58() {
+            Ok(x) => x,
+            Err(_) => return Err(From::from(format!("{} isn't valid base58", encoded))),
+        }
+    } else if BASE64_PREFIXES.contains(&prefix) {
+        base64::decode(&encoded[3..])?
+    } else {
+        return Err(From::from(format!("{} has an unknown prefix", encoded)));
+    };
+    if data.len() < 4 {
+        return Err(From::from(format!("{} is too short", encoded)));
+    }
+    let (value, check) = data.split_at(data.len() - 4);
+    if checksum(value) != check {
+        return Err(From::from(format!("{} has a bad checksum", encoded)));
+    }
+    Ok((String::from(prefix), value.to_vec()))
+}
+
+/*
+ * The value of "pp_...", which must have this prefix.
+ */
+pub fn decode_as(prefix: &str, encoded: &str) -> Result<Vec<u8>, Box<std::error::Error>> {
+    let (actual, value) = decode(encoded)?;
+    if actual != prefix {
+        return Err(From::from(format!("{} should start with {}_", encoded, prefix)));
+    }
+    Ok(value)
+}
+
+pub fn encode(prefix: &str, value: &[u8]) -> String {
+    let mut data = value.to_vec();
+    data.extend(checksum(value));
+    if BASE58_PREFIXES.contains(&prefix) {
+        format!("{}_{}", prefix, data.to_base58())
+    } else {
+        format!("{}_{}", prefix, base64::encode(&data))
+    }
+}
+
+/*
+ * In serialized objects, the accounts, names etc. that a transaction
+ * refers to are 33 byte ids: a tag saying what kind of thing it is,
+ * followed by its 32 byte key or hash.
+ */
+const ID_TAGS: [(u8, &str); 6] =
+    [(1, "ak"), (2, "nm"), (3, "cm"), (4, "ok"), (5, "ct"), (6, "ch")];
+
+pub fn encode_id(id: &str) -> Result<Vec<u8>, Box<std::error::Error>> {
+    let (prefix, value) = decode(id)?;
+    let tag = match ID_TAGS.iter().find(|x| x.1 == prefix) {
+        Some(x) => x.0,
+        None => return Err(From::from(format!("{} isn't an id", id))),
+    };
+    if value.len() != 32 {
+        return Err(From::from(format!("{} isn't 32 bytes", id)));
+    }
+    let mut bytes = vec!(tag);
+    bytes.extend(value);
+    Ok(bytes)
+}
+
+pub fn decode_id(bytes: &[u8]) -> Result<String, Box<std::error::Error>> {
+    if bytes.len() != 33 {
+        return Err(From::from(format!("Id is {} bytes, not 33", bytes.len())));
+    }
+    match ID_TAGS.iter().find(|x| x.0 == bytes[0]) {
+        Some(x) => Ok(encode(x.1, &bytes[1..])),
+        None => Err(From::from(format!("Unknown id tag {}", bytes[0]))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "ak_16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYG";
+
+    #[test]
+    fn round_trips() {
+        let value: Vec<u8> = (0..32).collect();
+        assert_eq!(encode("ak", &value), ACCOUNT);
+        assert_eq!(decode(ACCOUNT).unwrap(), (String::from("ak"), value.clone()));
+        assert_eq!(encode("ba", b"hello"), "ba_aGVsbG+Vlcnf");
+        assert_eq!(decode_as("ba", "ba_aGVsbG+Vlcnf").unwrap(), b"hello".to_vec());
+        let mut id = vec!(1);
+        id.extend(value);
+        assert_eq!(encode_id(ACCOUNT).unwrap(), id);
+        assert_eq!(decode_id(&id).unwrap(), ACCOUNT);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let bad = [
+            "", "ak", "ak_", "akx16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYG",
+            // bad checksum
+            "ak_16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYH",
+            "xx_16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYG",
+            "ak_0OIl", "ba_!!!!", "ak_1",
+            // multibyte characters where the prefix should be
+            "aéb", "é_x", "aé_x", "日本_", "ak_日本語",
+            "ak_'; DROP TABLE transactions; --",
+        ];
+        for x in bad.iter() {
+            assert!(decode(x).is_err(), "{} decoded", x);
+        }
+        assert!(decode_as("kh", ACCOUNT).is_err());
+        assert!(encode_id("ba_aGVsbG+Vlcnf").is_err());
+        assert!(decode_id(&[1, 2, 3]).is_err());
+        assert!(decode_id(&[9; 33]).is_err());
+    }
+}
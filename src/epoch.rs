@@ -3,7 +3,6 @@ use curl::easy::{Easy, List};
 use diesel::pg::PgConnection;
 use diesel::sql_types::*;
 use dotenv::dotenv;
-use postgres::{Connection, TlsMode};
 use r2d2::{Pool, PooledConnection};
 use r2d2_diesel::ConnectionManager;
 use regex::Regex;
@@ -18,22 +17,6 @@ use models::InsertableMicroBlock;
 use models::JsonKeyBlock;
 use models::JsonTransaction;
 
-pub fn establish_sql_connection() -> postgres::Connection {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    Connection::connect(database_url, TlsMode::None).unwrap()
-}
-
-pub fn get_missing_heights(height: i64) -> Vec<i32> {
-    let sql = format!("SELECT * FROM generate_series(0,{}) s(i) WHERE NOT EXISTS (SELECT height FROM key_blocks WHERE height = s.i)", height);
-    debug!("{}", &sql);
-    let mut missing_heights = Vec::new();
-    for row in &establish_sql_connection().query(&sql, &[]).unwrap() {
-        missing_heights.push(row.get(0));
-    }
-    missing_heights
-}
-
 pub fn establish_connection(size: u32) -> Arc<Pool<ConnectionManager<PgConnection>>> {
     dotenv().ok(); // Grabbing ENV vars
     debug!("Making new pool size {}", size);
@@ -0,0 +1,205 @@
+use diesel::pg::PgConnection;
+use diesel::sql_query;
+use diesel::sql_types::*;
+use diesel::RunQueryDsl;
+use std;
+use std::collections::BTreeMap;
+
+use models::KeyBlock;
+use serialization;
+
+/*
+ * The node won't accept a transaction unless its fee pays for its gas
+ * at its minimum gas price or more. That is MINIMUM_GAS_PRICE, from
+ * the node's governance rules in Roma, unless it has been configured
+ * to want more, so it can be set with --min-gas-price. A transaction's
+ * gas is the base gas for its type plus GAS_PER_BYTE for each byte of
+ * the unsigned transaction. Contract transactions pay for the gas they
+ * use on top of that, at their own gas_price, out of the sender's
+ * balance rather than the fee.
+ */
+pub const MINIMUM_GAS_PRICE: i64 = 1;
+pub const BASE_GAS: i64 = 15_000;
+pub const GAS_PER_BYTE: i64 = 20;
+
+// The most generations a fee estimate looks back over.
+pub const MAX_GENERATIONS: i64 = 1000;
+
+pub fn base_gas(tx_type: &str) -> i64 {
+    match tx_type {
+        "ContractCreateTx" => 5 * BASE_GAS,
+        "ContractCallTx" => 30 * BASE_GAS,
+        _ => BASE_GAS,
+    }
+}
+
+pub fn gas(tx_type: &str, size: i64) -> i64 {
+    base_gas(tx_type) + GAS_PER_BYTE * size
+}
+
+pub fn min_fee(tx_type: &str, size: i64, min_gas_price: i64) -> i64 {
+    min_gas_price * gas(tx_type, size)
+}
+
+/*
+ * The gas of a transaction whose signed form, the size we store, is
+ * signed_size bytes, which is charged on the transaction without its
+ * signatures.
+ */
+pub fn signed_tx_gas(tx_type: &str, signed_size: i64, signature_count: usize) -> Option<i64> {
+    serialization::unsigned_tx_size(signed_size as usize, signature_count)
+        .map(|x| gas(tx_type, x as i64))
+}
+
+#[derive(QueryableByName)]
+struct MinedFee {
+    #[sql_type = "Text"]
+    tx_type: String,
+    #[sql_type = "BigInt"]
+    fee: i64,
+    #[sql_type = "Integer"]
+    size: i32,
+    #[sql_type = "Text"]
+    signatures: String,
+    #[sql_type = "Nullable<BigInt>"]
+    gas_price: Option<i64>,
+}
+
+impl MinedFee {
+    fn gas(&self) -> Option<i64> {
+        let count = self.signatures.split(|c| c == ' ' || c == ',')
+            .filter(|x| !x.is_empty())
+            .count();
+        signed_tx_gas(&self.tx_type, self.size as i64, count)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Percentiles {
+    pub min: i64,
+    pub median: i64,
+    pub p90: i64,
+}
+
+impl Percentiles {
+    /*
+     * Nearest rank, so every value is one which was actually paid.
+     */
+    fn from_values(mut values: Vec<i64>) -> Option<Percentiles> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort();
+        let rank = |p: f64| values[((p * values.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Percentiles {
+            min: values[0],
+            median: rank(0.5),
+            p90: rank(0.9),
+        })
+    }
+}
+
+/*
+ * What transactions of one type paid. gas_price is the fee divided by
+ * the transaction's gas, i.e. what it paid per unit over the minimum
+ * rules, and contract_gas_price is the gas_price contract transactions
+ * offered for running the contract.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct TypeFeeEstimate {
+    pub tx_type: String,
+    pub count: usize,
+    pub base_gas: i64,
+    pub fee: Percentiles,
+    pub gas_price: Percentiles,
+    pub contract_gas_price: Option<Percentiles>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub from_height: i64,
+    pub to_height: i64,
+    pub minimum_gas_price: i64,
+    pub gas_per_byte: i64,
+    pub gas_price: Option<Percentiles>,
+    pub by_type: Vec<TypeFeeEstimate>,
+}
+
+impl FeeEstimate {
+    /*
+     * Over the transactions mined in the last `generations`
+     * generations, leaving out any whose size we don't know. To pay
+     * the median for a transaction of n bytes unsigned, set its fee to
+     * gas_price.median * (base_gas + gas_per_byte * n).
+     */
+    pub fn for_recent_generations(conn: &PgConnection, generations: i64, min_gas_price: i64) ->
+        Result<FeeEstimate, Box<std::error::Error>>
+    {
+        let to_height = KeyBlock::top_height(conn)?;
+        let from_height = std::cmp::max(to_height - generations + 1, 0);
+        let mined: Vec<MinedFee> = sql_query(
+            "SELECT t.tx_type, t.fee, t.size, t.signatures, (t.tx->>'gas_price')::BIGINT AS gas_price \
+             FROM transactions t, micro_blocks m, key_blocks k \
+             WHERE t.micro_block_id = m.id AND m.key_block_id = k.id AND t.size > 0 \
+             AND k.height >= $1 AND k.height <= $2")
+            .bind::<BigInt, _>(from_height)
+            .bind::<BigInt, _>(to_height)
+            .load(conn)?;
+        let mut all_gas_prices = vec!();
+        let mut by_type: BTreeMap<String, Vec<(&MinedFee, i64)>> = BTreeMap::new();
+        for m in &mined {
+            let tx_gas = match m.gas() {
+                Some(x) => x,
+                None => continue,
+            };
+            all_gas_prices.push(m.fee / tx_gas);
+            by_type.entry(m.tx_type.clone()).or_insert(vec!()).push((m, tx_gas));
+        }
+        let mut estimates = vec!();
+        for (_tx_type, txs) in by_type {
+            let fees = txs.iter().map(|&(x, _)| x.fee).collect();
+            let gas_prices = txs.iter().map(|&(x, g)| x.fee / g).collect();
+            let contract_gas_prices = txs.iter().filter_map(|&(x, _)| x.gas_price).collect();
+            estimates.push(TypeFeeEstimate {
+                base_gas: base_gas(&_tx_type),
+                tx_type: _tx_type,
+                count: txs.len(),
+                fee: Percentiles::from_values(fees).unwrap(),
+                gas_price: Percentiles::from_values(gas_prices).unwrap(),
+                contract_gas_price: Percentiles::from_values(contract_gas_prices),
+            });
+        }
+        Ok(FeeEstimate {
+            from_height,
+            to_height,
+            minimum_gas_price: min_gas_price,
+            gas_per_byte: GAS_PER_BYTE,
+            gas_price: Percentiles::from_values(all_gas_prices),
+            by_type: estimates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_fee_pays_for_gas() {
+        assert_eq!(min_fee("SpendTx", 0, MINIMUM_GAS_PRICE), 15_000);
+        assert_eq!(min_fee("SpendTx", 96, MINIMUM_GAS_PRICE), 16_920);
+        assert_eq!(min_fee("SpendTx", 96, 1_000_000), 16_920_000_000);
+        assert_eq!(min_fee("ContractCreateTx", 100, MINIMUM_GAS_PRICE), 77_000);
+        assert_eq!(min_fee("ContractCallTx", 100, MINIMUM_GAS_PRICE), 452_000);
+        assert_eq!(gas("NameClaimTx", 50), 16_000);
+    }
+
+    #[test]
+    fn percentiles_are_values_paid() {
+        assert!(Percentiles::from_values(vec!()).is_none());
+        let p = Percentiles::from_values(vec!(5)).unwrap();
+        assert_eq!((p.min, p.median, p.p90), (5, 5, 5));
+        let p = Percentiles::from_values((1..11).rev().collect()).unwrap();
+        assert_eq!((p.min, p.median, p.p90), (1, 5, 9));
+    }
+}
@@ -0,0 +1,134 @@
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::http::RawStr;
+use rocket::request::FromParam;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std;
+use std::fmt;
+use std::io::Write;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use encoding;
+
+/*
+ * Check that encoded has one of the prefixes and a good checksum.
+ */
+fn check(encoded: &str, prefixes: &[&str]) -> Result<(), Box<std::error::Error>> {
+    let (prefix, _) = encoding::decode(encoded)?;
+    if !prefixes.contains(&prefix.as_str()) {
+        return Err(From::from(format!("{} should start with {}_", encoded,
+                                      prefixes.join("_ or "))));
+    }
+    Ok(())
+}
+
+/*
+ * A hash or id as the node encodes it, which can only be made from a
+ * string with one of its prefixes and a good checksum, whether it
+ * comes from JSON or a URL. Those read from the DB were checked on the
+ * way in, so aren't checked again. It derefs to the string.
+ */
+macro_rules! id_type {
+    ($name:ident, [$($prefix:expr),+]) => {
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+        #[sql_type = "Text"]
+        pub struct $name(String);
+
+        impl $name {
+            pub const PREFIXES: &'static [&'static str] = &[$($prefix),+];
+        }
+
+        impl FromStr for $name {
+            type Err = Box<std::error::Error>;
+
+            fn from_str(s: &str) -> Result<$name, Box<std::error::Error>> {
+                check(s, $name::PREFIXES)?;
+                Ok($name(String::from(s)))
+            }
+        }
+
+        impl Deref for $name {
+            type Target = String;
+
+            fn deref(&self) -> &String {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.0)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(|e: Box<std::error::Error>| de::Error::custom(e.to_string()))
+            }
+        }
+
+        impl ToSql<Text, Pg> for $name {
+            fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+                ToSql::<Text, Pg>::to_sql(&self.0, out)
+            }
+        }
+
+        impl FromSql<Text, Pg> for $name {
+            fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<$name> {
+                Ok($name(<String as FromSql<Text, Pg>>::from_sql(bytes)?))
+            }
+        }
+
+        impl<'a> FromParam<'a> for $name {
+            type Error = Box<std::error::Error>;
+
+            fn from_param(param: &'a RawStr) -> Result<$name, Box<std::error::Error>> {
+                param.percent_decode()?.parse()
+            }
+        }
+    }
+}
+
+id_type!(KeyBlockHash, ["kh"]);
+id_type!(MicroBlockHash, ["mh"]);
+id_type!(TxHash, ["th"]);
+id_type!(AccountId, ["ak"]);
+
+// what a block's prev_hash points at, which may be either kind
+id_type!(BlockHash, ["kh", "mh"]);
+
+// anything which can take part in a transaction
+id_type!(ParticipantId, ["ak", "ct", "ok", "nm", "ch"]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_prefixes() {
+        let key_block = encoding::encode("kh", &[0; 32]);
+        assert!(key_block.parse::<KeyBlockHash>().is_ok());
+        assert!(key_block.parse::<MicroBlockHash>().is_err());
+        assert!(encoding::encode("ct", &[0; 32]).parse::<ParticipantId>().is_ok());
+        assert!(encoding::encode("th", &[0; 32]).parse::<ParticipantId>().is_err());
+        assert!("kh_".parse::<KeyBlockHash>().is_err());
+    }
+
+    #[test]
+    fn block_hashes_are_either_kind() {
+        assert!(encoding::encode("kh", &[0; 32]).parse::<BlockHash>().is_ok());
+        assert!(encoding::encode("mh", &[0; 32]).parse::<BlockHash>().is_ok());
+        assert!(encoding::encode("th", &[0; 32]).parse::<BlockHash>().is_err());
+    }
+}
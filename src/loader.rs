@@ -1,32 +1,79 @@
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::pg::PgConnection;
 use diesel::query_builder::SqlQuery;
 use diesel::query_dsl::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel::sql_query;
-use std::slice::SliceConcatExt;
-use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
-use std::thread;
+use std::time::SystemTime;
 
 use epoch;
 use epoch::*;
 use models::*;
+use queue::{CommitOrder, QueuedHeight};
+use supervisor::{Shutdown, Supervisor};
 //use super::schema::transactions;
 use super::schema::key_blocks;
 use super::schema::key_blocks::dsl::*;
+use super::schema::transactions;
 use super::schema::transactions::dsl::*;
 
+use serde::de::{Deserialize, DeserializeOwned};
 use serde_json;
+use std::fmt;
 
 use r2d2::Pool;
 use r2d2_diesel::ConnectionManager;
+
+/*
+ * Something the node sent which doesn't fit our models. The raw JSON
+ * is kept so that it can be looked at if the height is dead-lettered.
+ */
+#[derive(Debug)]
+pub struct UnexpectedResponse {
+    pub error: String,
+    pub raw_response: String,
+}
+
+impl fmt::Display for UnexpectedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unexpected response from node: {}", self.error)
+    }
+}
+
+impl std::error::Error for UnexpectedResponse {
+    fn description(&self) -> &str {
+        "Unexpected response from node"
+    }
+}
+
+fn parse_response<T: DeserializeOwned>(value: serde_json::Value) ->
+    Result<T, Box<std::error::Error>>
+{
+    match T::deserialize(&value) {
+        Ok(x) => Ok(x),
+        Err(e) => Err(Box::new(UnexpectedResponse {
+            error: format!("{}", e),
+            raw_response: value.to_string(),
+        })),
+    }
+}
+
+/*
+ * Everything the node told us about one generation. It is fetched in
+ * full before anything is written, so that the DB writes can happen in
+ * a single transaction.
+ */
+pub struct FullGeneration {
+    pub key_block: JsonKeyBlock,
+    pub micro_blocks: Vec<(InsertableMicroBlock, JsonTransactionList)>,
+}
+
 pub struct BlockLoader {
     epoch: Epoch,
     pub connection: Arc<Pool<ConnectionManager<PgConnection>>>, // DB connection
-    rx: std::sync::mpsc::Receiver<i64>,
-    pub tx: std::sync::mpsc::Sender<i64>,
+    pub commit_order: Arc<CommitOrder>,
 }
 
 impl BlockLoader {
@@ -36,68 +83,122 @@ impl BlockLoader {
     pub fn new(
         connection: Arc<Pool<ConnectionManager<PgConnection>>>,
         epoch_url: String,
+        commit_order: Arc<CommitOrder>,
     ) -> BlockLoader {
-        let (_tx, rx): (Sender<i64>, Receiver<i64>) = mpsc::channel();
         let epoch = Epoch::new(epoch_url.clone(), 1);
         BlockLoader {
-            epoch, connection, rx, tx: _tx,
+            epoch, connection, commit_order,
         }
     }
 
     /*
-     * We walk backward through the chain loading generations from the
-     * DB, and requesting them from the chain. We pause 1 second
-     * between each check, and only check 500 blocks (~1 day)
-     * back. For each pair of blocks we compare them using their eq()
-     * mehods. If false we delete the block from the DB (which
-     * cascades to delete the microblocks and transactions), and put
-     * the height onto the load queue.
+     * We walk back from the lower of the node's top and ours along the
+     * prev_key_hash links until we reach a height where the DB holds
+     * the same key block. Heights above our top are left to the
+     * loader. Every height on the way where the DB holds a different
+     * key block has been forked, so we orphan the block, its micro
+     * blocks and transactions, and put the height onto the load
+     * queue. This costs one request per height walked, and finds
+     * forks of any depth. Whatever we find in one pass is rolled back
+     * and recorded as a single reorg, in one DB transaction.
      *
-     * TODO: disassociate the TXs from the micro-blocks and keep them
-     * for reporting purposes.
+     * Once we reach a key block we agree on, the one above it must
+     * point (through prev_hash) at the last micro block we hold for
+     * its generation, otherwise its micro blocks have changed and it
+     * goes back on the queue too.
      */
-    pub fn detect_forks(epoch: &Epoch, _tx: &std::sync::mpsc::Sender<i64>) {
-        let conn = epoch.get_connection().unwrap();
-        let mut _height = KeyBlock::top_height(&conn).unwrap();
-        let stop_height = _height - 500; // a day, more or less
+    pub fn detect_forks(epoch: &Epoch) -> Result<(), Box<std::error::Error>> {
+        let conn = epoch.get_connection()?;
+        let node_top = key_block_from_json(epoch.latest_key_block()?)?;
+        if !KeyBlock::exists_at_or_below(&conn, node_top.height) {
+            debug!("Nothing in DB at or below height {}", node_top.height);
+            return Ok(());
+        }
+        let db_top = KeyBlock::top_height(&conn)?;
+        let (mut child, mut node_block) = if node_top.height > db_top {
+            let above = key_block_from_json(epoch.get_key_block_by_height(db_top + 1)?)?;
+            let top = key_block_from_json(epoch.get_key_block_by_hash(&above.prev_key_hash)?)?;
+            (Some(above), top)
+        } else {
+            (None, node_top)
+        };
+        let mut forked_heights = vec!();
+        let mut old_hashes = vec!();
+        let mut new_hashes = vec!();
+        let mut changed_generation = None;
         loop {
-            if _height <= stop_height {
+            let _height = node_block.height;
+            match KeyBlock::load_at_height(&conn, _height) {
+                Some(ref db_block) if db_block.hash == node_block.hash => {
+                    debug!("Block checks out at height {}", _height);
+                    if let Some(ref c) = child {
+                        if !MicroBlock::generation_ends_with(&conn, db_block, &c.prev_hash)? {
+                            info!("Micro blocks changed at height {}", _height);
+                            changed_generation = Some(_height);
+                        }
+                    }
+                    break;
+                },
+                Some(db_block) => {
+                    info!("Fork found at height {}: {} replaced by {}",
+                          _height, db_block.hash, node_block.hash);
+                    forked_heights.push(_height);
+                    old_hashes.push(db_block.hash.to_string());
+                    new_hashes.push(node_block.hash.to_string());
+                },
+                None => {
+                    if !KeyBlock::exists_at_or_below(&conn, _height) {
+                        debug!("Nothing in DB at or below height {}", _height);
+                        break;
+                    }
+                    QueuedHeight::enqueue(&conn, _height, "missing")?;
+                },
+            };
+            if _height == 0 {
                 break;
             }
-            let jg: JsonGeneration = match
-                JsonGeneration::get_generation_at_height(&conn, _height)  {
-                    Some(x) => x,
-                    None => {
-                        info!("Couldn't load generation {} from DB", _height);
-                        _height -= 1;
-                        continue;
-                    }
-                };
-            let gen_from_server: JsonGeneration =
-                serde_json::from_value(epoch.get_generation_at_height(
-                    _height).unwrap()).unwrap();
-            if ! jg.eq(&gen_from_server) {
-                info!("Couldn't load block from chain at height {}", _height);
-                BlockLoader::invalidate_block_at_height(_height, &conn, &_tx);
-                _height -= 1;
-                continue;
-            }                
-            debug!("Block checks out at height {}", _height);
-            _height -= 1; // only sleep if no fork found.
-            thread::sleep(std::time::Duration::new(2,0));
+            let parent = key_block_from_json(
+                epoch.get_key_block_by_hash(&node_block.prev_key_hash)?)?;
+            child = Some(node_block);
+            node_block = parent;
         }
+        conn.transaction::<(), Box<std::error::Error>, _>(|| {
+            if let Some(_height) = changed_generation {
+                QueuedHeight::enqueue(&conn, _height, "fork")?;
+            }
+            if forked_heights.is_empty() {
+                return Ok(());
+            }
+            let mut transactions_affected = 0;
+            for _height in &forked_heights {
+                transactions_affected += BlockLoader::invalidate_block_at_height(*_height, &conn)?;
+            }
+            InsertableReorg {
+                from_height: *forked_heights.iter().min().unwrap(),
+                to_height: *forked_heights.iter().max().unwrap(),
+                old_hashes,
+                new_hashes,
+                transactions_affected: transactions_affected as i32,
+                kind: String::from("key"),
+            }.save(&conn)?;
+            Ok(())
+        })
     }
 
     /*
-     * Delete a key block at height (which causes the deletion of the
-     * associated micro blocks and transaactions, and then adds the
-     * height to the queue of heights to be loaded.
+     * Move the key block at height, its micro blocks and their
+     * transactions to the orphan tables, and then add the height to
+     * the queue of heights to be loaded. Returns the number of
+     * transactions orphaned. This should be called inside a
+     * transaction, so a failure leaves nothing half done.
      */   
-    pub fn invalidate_block_at_height(_height: i64, conn: &PgConnection,
-                                  _tx: &std::sync::mpsc::Sender<i64>) {
+    pub fn invalidate_block_at_height(_height: i64, conn: &PgConnection) ->
+        Result<usize, Box<std::error::Error>>
+    {
         debug!("Invalidating block at height {}", _height);
-        _tx.send(_height).unwrap();
-        diesel::delete(key_blocks.filter(height.eq(&_height))).execute(conn).unwrap();
+        let count = orphan_generation(conn, _height)?;
+        QueuedHeight::enqueue(conn, _height, "fork")?;
+        Ok(count)
     }
     
     /*
@@ -111,25 +212,74 @@ impl BlockLoader {
             serde_json::from_value(self.epoch.get_pending_transaction_list().unwrap()).unwrap();
         let mut hashes_in_mempool = vec!();
         for i in 0..trans.transactions.len() {
-            match self.store_or_update_transaction(&conn, &trans.transactions[i], None) {
-                Ok(x) => (),
-                Err(x) => error!("Failed to insert transaction {}", trans.transactions[i].hash),
+            match self.store_or_update_transaction(&conn, &trans.transactions[i], None, None, None) {
+                Ok(_) => (),
+                Err(e) => error!("Failed to insert transaction {}: {:?}", trans.transactions[i].hash, e),
             }
-            hashes_in_mempool.push(format!("'{}'", trans.transactions[i].hash));
+            hashes_in_mempool.push(trans.transactions[i].hash.to_string());
         }
-        let sql = format!(
-            "UPDATE transactions SET VALID=FALSE WHERE \
-             micro_block_id IS NULL AND \
-             hash NOT IN ({})",
-            hashes_in_mempool.join(", "));
-        epoch::establish_sql_connection().execute(&sql, &[]);
+        let dropped = diesel::update(
+            transactions::table
+                .filter(transactions::micro_block_id.is_null())
+                .filter(transactions::valid.eq(true))
+                .filter(transactions::hash.ne_all(&hashes_in_mempool)))
+            .set(transactions::valid.eq(false))
+            .returning(transactions::hash)
+            .get_results::<String>(&*conn);
+        match dropped {
+            Ok(hashes) => for h in hashes {
+                if let Err(e) = InsertableTransactionEvent::new(&h, "dropped").save(&conn) {
+                    error!("Failed to record dropped transaction {}: {:?}", h, e);
+                }
+            },
+            Err(e) => error!("Failed to invalidate dropped transactions: {:?}", e),
+        };
     }        
 
+    /*
+     * The newest generation is still being added to, so we compare
+     * its micro blocks with what the node has and queue it for
+     * loading again whenever they differ. When a new key block
+     * arrives, the generation below it is queued one last time if it
+     * doesn't end where the new key block says it does, to pick up
+     * micro blocks which arrived after we last looked.
+     */
+    pub fn refresh_top_generation(epoch: &Epoch) -> Result<(), Box<std::error::Error>> {
+        let conn = epoch.get_connection()?;
+        let current: JsonGeneration = serde_json::from_value(epoch.current_generation()?)?;
+        let _height = current.key_block.height;
+        if _height > 0 {
+            if let Some(prev) = KeyBlock::load_at_height(&conn, _height - 1) {
+                if !MicroBlock::generation_ends_with(&conn, &prev, &current.key_block.prev_hash)? {
+                    debug!("Generation {} has changed since it was loaded", _height - 1);
+                    QueuedHeight::enqueue(&conn, _height - 1, "refresh")?;
+                }
+            }
+        }
+        let mut ours = match KeyBlock::load_at_height(&conn, _height) {
+            Some(ref kb) if kb.hash == current.key_block.hash =>
+                MicroBlock::hashes_for_key_block(&conn, kb.id)?,
+            _ => {
+                QueuedHeight::enqueue(&conn, _height, "refresh")?;
+                return Ok(());
+            },
+        };
+        let mut theirs = current.micro_blocks.clone();
+        ours.sort();
+        theirs.sort();
+        if ours != theirs {
+            debug!("Top generation {} has {} micro blocks, we have {}",
+                   _height, theirs.len(), ours.len());
+            QueuedHeight::enqueue(&conn, _height, "refresh")?;
+        }
+        Ok(())
+    }
+
     /*
      * this method scans the blocks from the heighest reported by the
      * node to the highest in the DB, filling in the gaps.
      */
-    pub fn scan(epoch: &Epoch, _tx: &std::sync::mpsc::Sender<i64>) {
+    pub fn scan(epoch: &Epoch) {
         let connection = epoch.get_connection().unwrap();
         let top_block_chain = key_block_from_json(epoch.latest_key_block().unwrap()).unwrap();
         let top_block_db = KeyBlock::top_height(&connection).unwrap();
@@ -148,9 +298,10 @@ impl BlockLoader {
             }
             if !KeyBlock::height_exists(&connection, _height) {
                 debug!("Fetching block {}", _height);
-                match _tx.send(_height) {
-                    Ok(x) => debug!("Success: {:?}", x),
-                    Err(e) => error!("Error fetching block at height {}: {:?}", _height, e),
+                match QueuedHeight::enqueue(&connection, _height, "scan") {
+                    Ok(true) => (),
+                    Ok(false) => debug!("Height {} already queued", _height),
+                    Err(e) => error!("Error queueing height {}: {:?}", _height, e),
                 };
             } else {
                 info!("Block already in DB at height {}", _height);
@@ -160,72 +311,282 @@ impl BlockLoader {
     }
 
     /*
-     * At this height, load the key block, using the generations call
-     * to grab the block and all of its microblocks.
-     */    
-    fn load_blocks(&self, _height: i64) -> Result<i32, Box<std::error::Error>> {
-        let mut count = 0;
-        let connection = self.connection.get()?;
-        let mut generation: JsonGeneration = serde_json::from_value(
+     * Ask the node for the generation at this height, its micro blocks
+     * and their transactions. Nothing touches the DB here.
+     */
+    fn fetch_generation(&self, _height: i64) -> Result<FullGeneration, Box<std::error::Error>> {
+        let generation: JsonGeneration = parse_response(
             self.epoch.get_generation_at_height(_height)?)?;
-        let ib: InsertableKeyBlock = InsertableKeyBlock::from_json_key_block(&generation.key_block)?;
-        let key_block_id = ib.save(&connection)? as i32;
+        let mut micro_blocks = vec!();
         for mb_hash in &generation.micro_blocks {
-            let mut mb: InsertableMicroBlock = serde_json::from_value(
+            let mb: InsertableMicroBlock = parse_response(
                 self.epoch.get_micro_block_by_hash(&mb_hash)?)?;
-            mb.key_block_id = Some(key_block_id);
-            let _micro_block_id = mb.save(&connection).unwrap() as i32;
             let trans: JsonTransactionList =
-                serde_json::from_value(self.epoch.get_transaction_list_by_micro_block(&mb_hash)?)?;
-            for i in 0..trans.transactions.len() {
-                self.store_or_update_transaction(&connection, &trans.transactions[i],
-                                                 Some(_micro_block_id)).unwrap();
-            }
-            count += 1;
+                parse_response(self.epoch.get_transaction_list_by_micro_block(&mb_hash)?)?;
+            micro_blocks.push((mb, trans));
         }
-        Ok(count)
+        Ok(FullGeneration {
+            key_block: generation.key_block,
+            micro_blocks,
+        })
+    }
+
+    /*
+     * Write a whole generation in one DB transaction. The key block
+     * and micro blocks are upserted, so loading the same height twice
+     * is harmless and nobody ever sees half a generation. If we hold a
+     * different key block at this height, or micro blocks which the
+     * node no longer reports for it, they are orphaned first.
+     */
+    pub fn store_generation(&self, conn: &PgConnection, generation: &FullGeneration) ->
+        Result<i32, Box<std::error::Error>>
+    {
+        conn.transaction::<i32, Box<std::error::Error>, _>(|| {
+            let jb = &generation.key_block;
+            if let Some(existing) = KeyBlock::load_at_height(conn, jb.height) {
+                if existing.hash != jb.hash {
+                    info!("Key block at height {} replaced by {}", jb.height, jb.hash);
+                    let count = orphan_generation(conn, jb.height)?;
+                    InsertableReorg {
+                        from_height: jb.height,
+                        to_height: jb.height,
+                        old_hashes: vec!(existing.hash.to_string()),
+                        new_hashes: vec!(jb.hash.to_string()),
+                        transactions_affected: count as i32,
+                        kind: String::from("key"),
+                    }.save(conn)?;
+                }
+            }
+            let ib: InsertableKeyBlock = InsertableKeyBlock::from_json_key_block(jb)?;
+            let key_block_id = ib.save(conn)?;
+            let mb_hashes: Vec<String> =
+                generation.micro_blocks.iter().map(|x| x.0.hash.to_string()).collect();
+            let ours = MicroBlock::hashes_for_key_block(conn, key_block_id)?;
+            let (stale, orphaned) = InsertableMicroBlock::orphan_stale(conn, key_block_id, &mb_hashes)?;
+            if !stale.is_empty() {
+                info!("Micro fork at height {}: {:?} lost", jb.height, stale);
+                InsertableReorg {
+                    from_height: jb.height,
+                    to_height: jb.height,
+                    old_hashes: stale,
+                    new_hashes: mb_hashes.iter().filter(|x| !ours.contains(*x)).cloned().collect(),
+                    transactions_affected: orphaned as i32,
+                    kind: String::from("micro"),
+                }.save(conn)?;
+            }
+            let offender = match KeyBlock::load_at_height(conn, jb.height - 1) {
+                Some(x) => Some(x.miner),
+                None => None,
+            };
+            let mut count = 0;
+            for (_micro_block_index, (mb, trans)) in generation.micro_blocks.iter().enumerate() {
+                let mut mb = mb.clone();
+                mb.key_block_id = Some(key_block_id);
+                let _micro_block_id = mb.save(conn)?;
+                // the node hasn't always given micro block times
+                let _mined_at = match mb.time {
+                    0 => None,
+                    t => Some(from_millis(t)),
+                };
+                if let Some(pof) = InsertableProofOfFraud::from_micro_block(&mb, jb, offender.clone()) {
+                    pof.save(conn)?;
+                }
+                for i in 0..trans.transactions.len() {
+                    self.store_or_update_transaction(conn, &trans.transactions[i],
+                                                     Some(_micro_block_id), _mined_at,
+                                                     Some((_micro_block_index as i32, i as i32)))?;
+                }
+                count += 1;
+            }
+            Ok(count)
+        })
     }
 
     /*
      * transactions in the mempool won't have a micro_block_id, so as we scan the chain we may 
      * need to insert them, or update them with the id of the micro block with which they're 
      * now associated. We may also need to move them to a different micro block, in the event
-     * of a fork.
+     * of a fork. Every such change is recorded in transaction_events.
+     * _mined_at is the time of the micro block, and _position is where
+     * the transaction is in the generation: the index of the micro
+     * block, and its index in the micro block.
      */
     pub fn store_or_update_transaction(&self, conn: &PgConnection,
                                        trans: &JsonTransaction,
-                                       _micro_block_id: Option<i32>
+                                       _micro_block_id: Option<i32>,
+                                       _mined_at: Option<SystemTime>,
+                                       _position: Option<(i32, i32)>,
     ) ->
         Result<i32, Box<std::error::Error>>
     {
-        let sql = format!("select * from transactions where hash = '{}' limit 1", &trans.hash);
-        debug!("{}", sql);
-        let mut results: Vec<Transaction> = sql_query(sql).
-            // bind::<diesel::sql_types::Text, _>(trans.hash.clone()).
+        let mut results: Vec<Transaction> =
+            sql_query("select * from transactions where hash = $1 limit 1").
+            bind::<diesel::sql_types::Text, _>(&trans.hash).
             get_results(conn)?;
         match results.pop() {
             Some(x) => {
                 debug!("Found {}", &trans.hash);
-                diesel::update(&x).set(micro_block_id.eq(_micro_block_id));
+                match _micro_block_id {
+                    Some(_) if x.micro_block_id != _micro_block_id => {
+                        diesel::update(&x)
+                            .set((transactions::micro_block_id.eq(_micro_block_id),
+                                  transactions::block_height.eq(trans.block_height),
+                                  transactions::block_hash.eq(&trans.block_hash),
+                                  transactions::valid.eq(true),
+                                  transactions::mined_at.eq(_mined_at),
+                                  transactions::micro_block_index.eq(_position.map_or(-1, |x| x.0)),
+                                  transactions::transaction_index.eq(_position.map_or(-1, |x| x.1))))
+                            .execute(conn)?;
+                        BlockLoader::record_mined(conn, trans)?;
+                    },
+                    Some(_) if x.mined_at != _mined_at ||
+                        _position.map_or(false, |p| p != (x.micro_block_index, x.transaction_index)) => {
+                        // loaded again, e.g. to fill in times missing before
+                        diesel::update(&x)
+                            .set((transactions::mined_at.eq(_mined_at),
+                                  transactions::micro_block_index.eq(_position.map_or(-1, |x| x.0)),
+                                  transactions::transaction_index.eq(_position.map_or(-1, |x| x.1))))
+                            .execute(conn)?;
+                    },
+                    None if x.micro_block_id.is_none() && !x.valid => {
+                        // dropped, and now back in the mempool
+                        diesel::update(&x).set(transactions::valid.eq(true)).execute(conn)?;
+                        InsertableTransactionEvent::new(&trans.hash, "seen_in_mempool").save(conn)?;
+                    },
+                    _ => (),
+                };
                 Ok(x.id)
             },
             None => {
                 debug!("Not found {}", &trans.hash);
                 let _tx_type: String = from_json(&serde_json::to_string(&trans.tx["type"])?);
-                let _tx: InsertableTransaction =
+                let mut _tx: InsertableTransaction =
                     InsertableTransaction::from_json_transaction(&trans, _tx_type, _micro_block_id)?;
-                _tx.save(conn)
+                // a transaction mined again after a fork has been seen before
+                _tx.first_seen = match _micro_block_id {
+                    Some(_) => TransactionEvent::first_seen(conn, &trans.hash)?,
+                    None => Some(SystemTime::now()),
+                };
+                _tx.mined_at = _mined_at;
+                if let Some((_micro_block_index, _transaction_index)) = _position {
+                    _tx.micro_block_index = _micro_block_index;
+                    _tx.transaction_index = _transaction_index;
+                }
+                let _id = _tx.save(conn)?;
+                match _micro_block_id {
+                    Some(_) => BlockLoader::record_mined(conn, trans)?,
+                    None => InsertableTransactionEvent::new(&trans.hash, "seen_in_mempool").save(conn)?,
+                };
+                Ok(_id)
             },
         }
     }
 
     /*
-     * The very simple function which pulls heights from the queue and
-     * loads them into the DB
+     * A transaction found in a block is mined the first time, and
+     * re_mined if it has been in one before, which means that block
+     * was lost to a fork. Either way its sender's nonce moves on.
+     */
+    fn record_mined(conn: &PgConnection, trans: &JsonTransaction) ->
+        Result<(), Box<std::error::Error>>
+    {
+        let event = match TransactionEvent::was_mined(conn, &trans.hash)? {
+            true => "re_mined",
+            false => "mined",
+        };
+        let mut e = InsertableTransactionEvent::new(&trans.hash, event);
+        e.block_height = Some(trans.block_height);
+        e.micro_block_hash = Some(trans.block_hash.clone());
+        e.save(conn)?;
+        AccountNonce::record(conn, &trans.tx)
+    }
+
+    /*
+     * Pull heights from the queue and load them into the DB, sleeping
+     * for a second whenever the queue is empty, until we are asked to
+     * shut down. A generation which is being loaded is always
+     * finished first.
+     */
+    pub fn start(&self, shutdown: &Shutdown) {
+        while !shutdown.is_set() {
+            let connection = match self.connection.get() {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Couldn't get DB connection: {:?}", e);
+                    shutdown.sleep(std::time::Duration::new(1, 0));
+                    continue;
+                }
+            };
+            match self.load_next(&connection) {
+                Ok(true) => (),
+                Ok(false) => {
+                    shutdown.sleep(std::time::Duration::new(1, 0));
+                },
+                Err(e) => error!("Error loading generation: {:?}", e),
+            };
+        }
+    }
+
+    /*
+     * Claim a height from the queue, load its generation and remove
+     * it from the queue, all in one DB transaction. Fetching from the
+     * node happens in parallel with the other workers, but the commit
+     * waits until every lower height in flight is done. A missing
+     * height is put back if a lower one is still missing and nobody
+     * is loading it, e.g. because it failed, so that the DB never has
+     * a gap below its top. If anything fails the height stays queued
+     * to be retried later, with the error recorded against it before
+     * the next height may commit. Returns false if there was nothing
+     * to do.
+     */
+    fn load_next(&self, conn: &PgConnection) -> Result<bool, Box<std::error::Error>> {
+        let mut in_flight = None;
+        let result = conn.transaction::<bool, Box<std::error::Error>, _>(|| {
+            let item = match QueuedHeight::claim(conn)? {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+            debug!("Loading height {} ({})", item.height, item.reason);
+            in_flight = Some(self.commit_order.start(item.height));
+            let generation = self.fetch_generation(item.height)?;
+            if let Some(ref x) = in_flight {
+                x.wait_turn();
+            }
+            if !KeyBlock::height_exists(conn, item.height) &&
+                QueuedHeight::gap_below(conn, item.height)? {
+                debug!("Putting height {} back until the heights below it are loaded",
+                       item.height);
+                return Ok(false);
+            }
+            self.store_generation(conn, &generation)?;
+            QueuedHeight::remove(conn, item.height)?;
+            Ok(true)
+        });
+        if let (&Some(ref x), &Err(ref e)) = (&in_flight, &result) {
+            let raw_response = match e.downcast_ref::<UnexpectedResponse>() {
+                Some(x) => Some(x.raw_response.clone()),
+                None => None,
+            };
+            QueuedHeight::record_failure(conn, x.height, &format!("{}", e), raw_response)?;
+        }
+        result
+    }
+
+    /*
+     * Start a pool of workers, each with its own DB connection and
+     * node client, all taking heights from the load_queue table.
      */
-    pub fn start(&self) {
-        for b in &self.rx {
-            self.load_blocks(b);
+    pub fn start_workers(epoch_url: &String, count: u32, supervisor: &Supervisor) {
+        let commit_order = Arc::new(CommitOrder::new());
+        for i in 0..count {
+            let url = epoch_url.clone();
+            let commit_order = commit_order.clone();
+            supervisor.spawn(&format!("loader-{}", i), move |shutdown| {
+                debug!("Starting loader worker {}", i);
+                let loader = BlockLoader::new(epoch::establish_connection(1), url.clone(),
+                                              commit_order.clone());
+                loader.start(shutdown);
+            });
         }
     }
 }
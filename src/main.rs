@@ -7,6 +7,7 @@
 
 extern crate rand;
 
+extern crate base64;
 extern crate bigdecimal;
 extern crate blake2b;
 extern crate crypto;
@@ -28,6 +29,7 @@ extern crate rocket;
 extern crate rocket_contrib;
 extern crate rocket_cors;
 extern crate rust_base58;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
@@ -36,98 +38,128 @@ use std::thread;
 extern crate itertools;
 
 extern crate futures;
-extern crate postgres;
 
+#[macro_use]
 extern crate clap;
 use clap::{App, Arg};
 
+extern crate ctrlc;
+
 use dotenv::dotenv;
 use std::env;
 
+pub mod encoding;
 pub mod epoch;
+pub mod fees;
+pub mod ids;
 pub mod loader;
+pub mod queue;
+pub mod rlp;
 pub mod schema;
+pub mod search;
+pub mod serialization;
 pub mod server;
+pub mod supervisor;
+pub mod validation;
 
 use loader::BlockLoader;
+use queue::{CommitOrder, DeadLetter, QueuedHeight};
 use server::MiddlewareServer;
+use std::sync::Arc;
+use std::time::Duration;
+use supervisor::Supervisor;
 
 pub mod models;
 
-fn start_blockloader(url: &String,  _tx: std::sync::mpsc::Sender<i64>) {
+fn start_blockloader(url: &String, supervisor: &Supervisor) {
     debug!("In start_blockloader()");
     let u = url.clone();
-    let u2 = url.clone();
-    thread::spawn(move || {
-        thread::spawn(move || {
-            let epoch = epoch::Epoch::new(u2.clone(), 1);
-            loop {
-                debug!("Scanning for new blocks");
-                loader::BlockLoader::scan(&epoch, &_tx);
-                debug!("Sleeping.");
-                thread::sleep_ms(40000);
+    supervisor.spawn("scan", move |shutdown| {
+        let epoch = epoch::Epoch::new(u.clone(), 1);
+        loop {
+            debug!("Scanning for new blocks");
+            loader::BlockLoader::scan(&epoch);
+            debug!("Sleeping.");
+            if !shutdown.sleep(Duration::new(40, 0)) {
+                return;
             }
-        });
+        }
     });
 }
     
-fn load_mempool(url: &String) {
+fn load_mempool(url: &String, supervisor: &Supervisor) {
     debug!("In load_mempool()");
     let u = url.clone();
-    let u2 = u.clone();
-    let loader = BlockLoader::new(epoch::establish_connection(1), String::from(u));
-    thread::spawn(move || {
-        let epoch = epoch::Epoch::new(u2.clone(), 1);
+    supervisor.spawn("mempool", move |shutdown| {
+        let loader = BlockLoader::new(epoch::establish_connection(1), u.clone(),
+                                      Arc::new(CommitOrder::new()));
+        let epoch = epoch::Epoch::new(u.clone(), 1);
         loop {
             loader.load_mempool(&epoch);
-            thread::sleep(std::time::Duration::new(5,0));
+            if !shutdown.sleep(Duration::new(5, 0)) {
+                return;
+            }
         }
     });
 }
 
 /*
- * This function does two things--initially it asks the DB for the
-* heights not present between 0 and the height returned by
-* /generations/current.  After it has quued all of them it spawns the
-* detect_forks thread, then it starts the blockloader, which does not
-* return.
-*/  
-fn fill_missing_heights(url: String, _tx: std::sync::mpsc::Sender<i64>) {
+ * Keeps the newest generation up to date as micro blocks are added to
+ * it.
+ */
+fn refresh_top_generation(url: &String, supervisor: &Supervisor) {
+    debug!("In refresh_top_generation()");
+    let u = url.clone();
+    supervisor.spawn("refresh", move |shutdown| {
+        let epoch = epoch::Epoch::new(u.clone(), 1);
+        loop {
+            match loader::BlockLoader::refresh_top_generation(&epoch) {
+                Ok(_) => (),
+                Err(e) => error!("Error refreshing top generation: {:?}", e),
+            };
+            if !shutdown.sleep(Duration::new(3, 0)) {
+                return;
+            }
+        }
+    });
+}
+
+/*
+ * Asks the DB for the heights not present between 0 and the height
+ * returned by /key-blocks/current, and queues all of them.
+ */  
+fn fill_missing_heights(url: &String, supervisor: &Supervisor) {
     debug!("In fill_missing_heights()");
     let u = url.clone();
-    let u2 = u.clone();
-    let handle = thread::spawn(move || {
-        let loader = BlockLoader::new(epoch::establish_connection(1), String::from(u));
-        let epoch = epoch::Epoch::new(u2.clone(), 1);
+    supervisor.spawn("fill_missing_heights", move |_| {
+        let epoch = epoch::Epoch::new(u.clone(), 1);
         let top_block = epoch::key_block_from_json(epoch.latest_key_block().unwrap()).unwrap();
-        let missing_heights = epoch::get_missing_heights(top_block.height);
-        for height in missing_heights {
-            debug!("Adding {} to load queue", &height);
-            _tx.send(height as i64);
-        }
-        detect_forks(&url.clone(), _tx.clone());
-        loader.start();
+        let conn = epoch.get_connection().unwrap();
+        let count = QueuedHeight::enqueue_missing(&conn, top_block.height).unwrap();
+        debug!("Added {} missing heights to load queue", count);
     });
 }
 
 /*
- * Detect forks iterates through the blocks in the DB asking for them and checking
- * that they match what we have in the DB. 
+ * Detect forks walks back from the top of the chain, checking that the
+ * blocks the node has match what we have in the DB.
  */
-fn detect_forks(url: &String, _tx: std::sync::mpsc::Sender<i64>) {
+fn detect_forks(url: &String, supervisor: &Supervisor) {
     debug!("In detect_forks()");
     let u = url.clone();
-    let u2 = u.clone();
-    thread::spawn(move || {
-        thread::spawn(move || {
-            let epoch = epoch::Epoch::new(u2.clone(), 1);
-            loop {
-                debug!("Going into fork detection");
-                loader::BlockLoader::detect_forks(&epoch, &_tx);
-                debug!("Sleeping.");
-                thread::sleep_ms(40000);
+    supervisor.spawn("detect_forks", move |shutdown| {
+        let epoch = epoch::Epoch::new(u.clone(), 1);
+        loop {
+            debug!("Going into fork detection");
+            match loader::BlockLoader::detect_forks(&epoch) {
+                Ok(_) => (),
+                Err(e) => error!("Error detecting forks: {:?}", e),
+            };
+            debug!("Sleeping.");
+            if !shutdown.sleep(Duration::new(40, 0)) {
+                return;
             }
-        });
+        }
     });
 }
 
@@ -167,32 +199,107 @@ fn main() {
                 .help("Populate DB")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("retry_dead_letters")
+                .long("retry-dead-letters")
+                .help("Queue the heights which failed to load to be tried again")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("backfill_sizes")
+                .long("backfill-sizes")
+                .help("Work out the serialized size of the transactions already loaded")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("verify_signatures")
+                .long("verify-signatures")
+                .value_name("NETWORK_ID")
+                .help("Check the signatures of posted transactions for this network.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min_gas_price")
+                .long("min-gas-price")
+                .value_name("GAS_PRICE")
+                .help("Lowest gas price the node takes transactions at. [default: 1]")
+                .takes_value(true)
+                .validator(|x| match x.parse::<i64>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(String::from("must be a whole number of at least 1")),
+                }),
+        )
+        .arg(
+            Arg::with_name("workers")
+                .short("w")
+                .long("workers")
+                .value_name("WORKERS")
+                .help("Number of loader workers.")
+                .takes_value(true)
+                .default_value("4")
+                .validator(|x| match x.parse::<u32>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err(String::from("must be a whole number of at least 1")),
+                }),
+        )
         .get_matches();
 
     
     let url = env::var("EPOCH_URL").expect("EPOCH_URL must be set").
-        to_string();;
+        to_string();
     let connection = epoch::establish_connection(1);
 
     let populate = matches.is_present("populate");
     let serve = matches.is_present("server");
+    let retry_dead_letters = matches.is_present("retry_dead_letters");
+    let backfill_sizes = matches.is_present("backfill_sizes");
+
+    if retry_dead_letters {
+        let count = DeadLetter::retry_all(&connection.get().unwrap()).unwrap();
+        info!("Queued {} dead-lettered heights to be loaded again", count);
+    }
+
+    if backfill_sizes {
+        let count = models::Transaction::backfill_sizes(&connection.get().unwrap()).unwrap();
+        info!("Corrected the size of {} transactions", count);
+    }
+
+    if !populate && !serve {
+        if !retry_dead_letters && !backfill_sizes {
+            warn!("Nothing to do!");
+        }
+        return;
+    }
 
     /*
-     * we start 3 populate processes--one queries for missing heights
-     * and works through that list, then exits. Another polls for
-     * new blocks to load, then sleeps and does it again, and yet 
-     * another reads the mempool (if available).
+     * SIGINT or SIGTERM asks the workers to stop. Each finishes the
+     * generation it is loading, and then we exit.
+     */
+    let supervisor = Arc::new(Supervisor::new());
+    let shutdown = supervisor.shutdown.clone();
+    ctrlc::set_handler(move || {
+        info!("Signal received");
+        shutdown.set();
+    }).expect("Error setting signal handler");
+
+    /*
+     * we start 5 kinds of populate process--one queries for missing
+     * heights and queues them, then exits. Another polls for new
+     * blocks to load, then sleeps and does it again, another keeps
+     * the newest generation up to date, another looks for forks, and
+     * yet another reads the mempool (if available). Meanwhile a pool
+     * of workers loads whatever has been queued.
      */
     if populate {
         let url = url.clone();
-        let loader = BlockLoader::new(epoch::establish_connection(1), url.clone());
-        load_mempool(&url);
-        fill_missing_heights(url.clone(), loader.tx.clone());
-        start_blockloader(&url, loader.tx.clone());
-        let handle = thread::spawn(move || {        
-            loader.start();
-        });
-        }
+        let workers = value_t!(matches, "workers", u32).unwrap_or_else(|e| e.exit());
+        load_mempool(&url, &supervisor);
+        fill_missing_heights(&url, &supervisor);
+        detect_forks(&url, &supervisor);
+        start_blockloader(&url, &supervisor);
+        refresh_top_generation(&url, &supervisor);
+        BlockLoader::start_workers(&url, workers, &supervisor);
+    }
 
     if serve {
         let ms: MiddlewareServer = MiddlewareServer {
@@ -200,13 +307,15 @@ fn main() {
             dest_url: url.to_string(),
             port: 3013,
             connection,
+            supervisor: supervisor.clone(),
+            network_id: matches.value_of("verify_signatures").map(String::from),
+            min_gas_price: matches.value_of("min_gas_price")
+                .map_or(fees::MINIMUM_GAS_PRICE, |x| x.parse().unwrap()),
         };
-        ms.start();
-    }
-    if !populate && !serve {
-        warn!("Nothing to do!");
-    }
-    loop {
-        thread::sleep_ms(40000);
+        thread::spawn(move || {
+            ms.start();
+        });
     }
+    supervisor.run_until_shutdown();
+    std::process::exit(0);
 }
@@ -1,12 +1,20 @@
 #![allow(proc_macro_derive_resolution_fallback)]
 
+use super::schema::account_nonces;
 use super::schema::key_blocks;
 use super::schema::key_blocks::dsl::*;
 use super::schema::micro_blocks;
+use super::schema::orphaned_transactions;
+use super::schema::proofs_of_fraud;
+use super::schema::reorgs;
+use super::schema::submissions;
+use super::schema::transaction_events;
+use super::schema::transaction_participants;
 use super::schema::transactions;
 
 use diesel::sql_query;
 use diesel::dsl::exists;
+use diesel::dsl::max;
 use diesel::dsl::select;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
@@ -16,23 +24,26 @@ extern crate serde_json;
 use serde_json::Number;
 
 use bigdecimal;
+use ids::{AccountId, BlockHash, KeyBlockHash, MicroBlockHash, TxHash};
 use bigdecimal::ToPrimitive;
+use serialization;
 use std;
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use epoch;
 
 #[derive(Queryable)]
 pub struct KeyBlock {
     pub id: i32,
-    pub hash: String,
+    pub hash: KeyBlockHash,
     pub height: i64,
-    pub miner: String,
-    pub beneficiary: String,
+    pub miner: AccountId,
+    pub beneficiary: AccountId,
     pub pow: String,
     pub nonce: bigdecimal::BigDecimal,
-    pub prev_hash: String,
-    pub prev_key_hash: String,
+    pub prev_hash: BlockHash,
+    pub prev_key_hash: KeyBlockHash,
     pub state_hash: String,
     pub target: i64,
     pub time: i64,
@@ -65,14 +76,10 @@ impl KeyBlock {
     }
 
     pub fn top_height(conn: &PgConnection) -> Result<i64, Box<std::error::Error>> {
-        let b = key_blocks::table
-            .order(key_blocks::height.desc())
-            .load::<KeyBlock>(conn)?;
-        let h = match b.first() {
-            Some(x) => x,
-            None => return Ok(0),
-        };
-        Ok(h.height)
+        let top: Option<i64> = key_blocks::table
+            .select(max(key_blocks::height))
+            .first(conn)?;
+        Ok(top.unwrap_or(0))
     }
 
     pub fn load_at_height(conn: &PgConnection, _height: i64) -> Option<KeyBlock> {
@@ -101,6 +108,24 @@ impl KeyBlock {
             };
         Some(blocks.pop()?)
     }
+
+    /*
+     * The most recent key block at or before _time, in milliseconds.
+     */
+    pub fn load_at_time(conn: &PgConnection, _time: i64) -> Option<KeyBlock> {
+        let mut blocks = match key_blocks::table
+            .filter(time.le(_time))
+            .order((time.desc(), height.desc()))
+            .limit(1)
+            .load::<KeyBlock>(conn) {
+                Ok(x) => x,
+                Err(y) => {
+                    error!("Error loading key block: {:?}", y);
+                    return None;
+                },
+            };
+        Some(blocks.pop()?)
+    }
             
     pub fn height_exists(conn: &PgConnection, h: i64) -> bool {
         match select(exists(key_blocks.filter(height.eq(h)))).get_result(conn) {
@@ -108,19 +133,26 @@ impl KeyBlock {
             _ => false,
         }
     }
+
+    pub fn exists_at_or_below(conn: &PgConnection, h: i64) -> bool {
+        match select(exists(key_blocks.filter(height.le(h)))).get_result(conn) {
+            Ok(result) => result,
+            _ => false,
+        }
+    }
 }
 
-#[derive(Insertable)]
+#[derive(Insertable, AsChangeset)]
 #[table_name = "key_blocks"]
 pub struct InsertableKeyBlock {
-    pub hash: String,
+    pub hash: KeyBlockHash,
     pub height: i64,
-    pub miner: String,
+    pub miner: AccountId,
     pub nonce: bigdecimal::BigDecimal,
-    pub beneficiary: String,
+    pub beneficiary: AccountId,
     pub pow: String,
-    pub prev_hash: String,
-    pub prev_key_hash: String,
+    pub prev_hash: BlockHash,
+    pub prev_key_hash: KeyBlockHash,
     pub state_hash: String,
     pub target: i64,
     pub time: i64,
@@ -128,11 +160,22 @@ pub struct InsertableKeyBlock {
 }
 
 impl InsertableKeyBlock {
+    /*
+     * Insert the key block, or overwrite the one already stored at
+     * this height, keeping its id so that the micro blocks which
+     * point at it stay attached.
+     */
     pub fn save(&self, conn: &PgConnection) -> Result<i32, Box<std::error::Error>> {
         use diesel::dsl::{insert_into, select};
         use diesel::RunQueryDsl;
         use schema::key_blocks::dsl::*;
-        let generated_ids: Vec<i32> = insert_into(key_blocks).values(self).returning(id).get_results(&*conn)?;
+        let generated_ids: Vec<i32> = insert_into(key_blocks)
+            .values(self)
+            .on_conflict(height)
+            .do_update()
+            .set(self)
+            .returning(id)
+            .get_results(&*conn)?;
         Ok(generated_ids[0])
     }
 
@@ -172,16 +215,16 @@ for these missing methods.
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 pub struct JsonKeyBlock {
-    pub hash: String,
+    pub hash: KeyBlockHash,
     pub height: i64,
-    pub miner: String,
-    pub beneficiary: String,
+    pub miner: AccountId,
+    pub beneficiary: AccountId,
     #[serde(default = "zero")]
     pub nonce: Number,
     #[serde(default = "zero_vec_i32")]
     pub pow: Vec<i32>,
-    pub prev_hash: String,
-    pub prev_key_hash: String,
+    pub prev_hash: BlockHash,
+    pub prev_key_hash: KeyBlockHash,
     pub state_hash: String,
     pub target: i64,
     pub time: i64,
@@ -245,14 +288,15 @@ pub struct MicroBlock {
     pub id: i32,
     #[serde(default = "option_i32")]
     pub key_block: Option<KeyBlock>,
-    pub hash: String,
+    pub hash: MicroBlockHash,
     pub pof_hash: String,
-    pub prev_hash: String,
-    pub prev_key_hash: String,
+    pub prev_hash: BlockHash,
+    pub prev_key_hash: KeyBlockHash,
     pub signature: String,
     pub state_hash: String,
     pub txs_hash: String,
     pub version: i32,
+    pub time: i64,
 }
 
 fn option_i32() -> Option<i32> {
@@ -260,45 +304,153 @@ fn option_i32() -> Option<i32> {
 }
 
 impl MicroBlock {
+    pub fn hashes_for_key_block(conn: &PgConnection, _key_block_id: i32) ->
+        Result<Vec<String>, Box<std::error::Error>>
+    {
+        let hashes = micro_blocks::table
+            .filter(micro_blocks::key_block_id.eq(_key_block_id))
+            .select(micro_blocks::hash)
+            .load::<String>(conn)?;
+        Ok(hashes)
+    }
+
+    /*
+     * Whether last_hash is the last micro block we hold for this key
+     * block's generation: it is ours, and none of ours follows it. A
+     * generation without micro blocks ends with the key block itself.
+     */
+    pub fn generation_ends_with(conn: &PgConnection, kb: &KeyBlock,
+                                last_hash: &String) ->
+        Result<bool, Box<std::error::Error>>
+    {
+        let ours = micro_blocks::table.filter(micro_blocks::key_block_id.eq(kb.id));
+        if *last_hash == *kb.hash {
+            let any: bool = select(exists(ours.clone())).get_result(conn)?;
+            return Ok(!any);
+        }
+        let present: bool =
+            select(exists(ours.clone().filter(micro_blocks::hash.eq(last_hash)))).get_result(conn)?;
+        let followed: bool =
+            select(exists(ours.filter(micro_blocks::prev_hash.eq(last_hash)))).get_result(conn)?;
+        Ok(present && !followed)
+    }
+
     pub fn get_microblock_hashes_for_key_block_hash(conn: &PgConnection,
                                                     kb_hash: &String) ->
         Option<Vec<String>>
     {
-        let sql = format!(
-            "SELECT hash FROM micro_blocks WHERE key_block_id={} ORDER BY hash",
-            kb_hash);
-        let mut micro_block_hashes = Vec::new();
-        for row in &epoch::establish_sql_connection().query(&sql, &[]).unwrap() {
-            micro_block_hashes.push(row.get(0));
-        }
-        Some(micro_block_hashes)
+        match micro_blocks::table
+            .inner_join(key_blocks::table)
+            .filter(key_blocks::hash.eq(kb_hash))
+            .select(micro_blocks::hash)
+            .order(micro_blocks::hash)
+            .load::<String>(conn) {
+                Ok(x) => Some(x),
+                Err(y) => {
+                    error!("Error loading micro block hashes: {:?}", y);
+                    None
+                },
+            }
     }
 }
 
-#[derive(Insertable)]
+#[derive(Insertable, AsChangeset)]
 #[table_name = "micro_blocks"]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct InsertableMicroBlock {
     pub key_block_id: Option<i32>,
-    pub hash: String,
+    pub hash: MicroBlockHash,
     pub pof_hash: String,
-    pub prev_hash: String,
-    pub prev_key_hash: String,
+    pub prev_hash: BlockHash,
+    pub prev_key_hash: KeyBlockHash,
     pub signature: String,
     pub state_hash: String,
     pub txs_hash: String,
     pub version: i32,
+    #[serde(default)]
+    pub time: i64,
 }
 
 impl InsertableMicroBlock {
+    /*
+     * Insert the micro block, or update the existing row with the
+     * same hash (e.g. when a generation is loaded a second time).
+     */
     pub fn save(&self, conn: &PgConnection) -> Result<i32, Box<std::error::Error>> {
         use diesel::dsl::{insert_into, select};
         use diesel::RunQueryDsl;
         use schema::micro_blocks::dsl::*;
-        let generated_ids: Vec<i32> =
-            insert_into(micro_blocks).values(self).returning(id).get_results(&*conn)?;
+        let generated_ids: Vec<i32> = insert_into(micro_blocks)
+            .values(self)
+            .on_conflict(hash)
+            .do_update()
+            .set(self)
+            .returning(id)
+            .get_results(&*conn)?;
         Ok(generated_ids[0])
     }
+
+    /*
+     * Orphan the micro blocks attached to a key block which are not
+     * in the list of hashes the node now reports for its generation,
+     * i.e. which have been lost to a micro fork. Returns their hashes
+     * and the number of transactions orphaned.
+     */
+    pub fn orphan_stale(conn: &PgConnection, _key_block_id: i32,
+                        current_hashes: &Vec<String>) ->
+        Result<(Vec<String>, usize), Box<std::error::Error>>
+    {
+        let stale: Vec<(i32, String)> = micro_blocks::table
+            .filter(micro_blocks::key_block_id.eq(_key_block_id))
+            .filter(micro_blocks::hash.ne_all(current_hashes))
+            .select((micro_blocks::id, micro_blocks::hash))
+            .load(conn)?;
+        let ids: Vec<i32> = stale.iter().map(|x| x.0).collect();
+        let count = orphan_micro_blocks(conn, &ids)?;
+        Ok((stale.into_iter().map(|x| x.1).collect(), count))
+    }
+}
+
+/*
+ * A micro block's header, as the node gives it.
+ */
+#[derive(QueryableByName, Serialize, Deserialize)]
+pub struct JsonMicroBlockHeader {
+    #[sql_type = "Text"]
+    pub hash: MicroBlockHash,
+    #[sql_type = "BigInt"]
+    pub height: i64,
+    #[sql_type = "Text"]
+    pub pof_hash: String,
+    #[sql_type = "Text"]
+    pub prev_hash: BlockHash,
+    #[sql_type = "Text"]
+    pub prev_key_hash: KeyBlockHash,
+    #[sql_type = "Text"]
+    pub signature: String,
+    #[sql_type = "Text"]
+    pub state_hash: String,
+    #[sql_type = "BigInt"]
+    pub time: i64,
+    #[sql_type = "Text"]
+    pub txs_hash: String,
+    #[sql_type = "Integer"]
+    pub version: i32,
+}
+
+impl JsonMicroBlockHeader {
+    pub fn load_at_hash(conn: &PgConnection, _hash: &String) ->
+        Result<Option<JsonMicroBlockHeader>, Box<std::error::Error>>
+    {
+        let mut headers: Vec<JsonMicroBlockHeader> = sql_query(
+            "SELECT m.hash, k.height, m.pof_hash, m.prev_hash, m.prev_key_hash, m.signature, \
+             m.state_hash, m.time_ AS time, m.txs_hash, m.version \
+             FROM micro_blocks m, key_blocks k \
+             WHERE m.key_block_id = k.id AND m.hash = $1")
+            .bind::<Text, _>(_hash)
+            .load(conn)?;
+        Ok(headers.pop())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -320,13 +472,13 @@ impl JsonGeneration {
             },
         };
         info!("Serving generation {} from DB", _height);
-        let sql = format!(
-            "SELECT hash FROM micro_blocks WHERE key_block_id={}",
-            key_block.id);
-        let mut micro_block_hashes = Vec::new();
-        for row in &epoch::establish_sql_connection().query(&sql, &[]).unwrap() {
-            micro_block_hashes.push(row.get(0));
-        }
+        let micro_block_hashes = match MicroBlock::hashes_for_key_block(conn, key_block.id) {
+            Ok(x) => x,
+            Err(y) => {
+                error!("Error loading micro block hashes: {:?}", y);
+                return None;
+            },
+        };
         Some(JsonGeneration {
             key_block: JsonKeyBlock::from_key_block(&key_block),
             micro_blocks: micro_block_hashes,
@@ -358,38 +510,115 @@ pub struct Transaction {
     pub micro_block_id: Option<i32>,
     pub block_height: i32,
     pub block_hash: String,
-    pub hash: String,
+    pub hash: TxHash,
     pub signatures: String,
     pub fee: i64,
     pub size: i32,
     pub tx: serde_json::Value,
+    pub valid: bool,
+    pub first_seen: Option<SystemTime>,
+    pub mined_at: Option<SystemTime>,
+    pub micro_block_index: i32,
+    pub transaction_index: i32,
 }
 
 impl Transaction {
+    /*
+     * Work out the size of every transaction again, for those stored
+     * before we knew how to, a thousand at a time. Returns the number
+     * changed.
+     */
+    pub fn backfill_sizes(conn: &PgConnection) -> Result<usize, Box<std::error::Error>> {
+        let mut last_id = 0;
+        let mut changed = 0;
+        loop {
+            let batch: Vec<Transaction> =
+                sql_query("select * from transactions where id > $1 order by id limit 1000")
+                .bind::<Integer, _>(last_id)
+                .load(conn)?;
+            if batch.is_empty() {
+                return Ok(changed);
+            }
+            for t in &batch {
+                let _size = serialized_size(&JsonTransaction::from_transaction(t));
+                if _size != t.size {
+                    diesel::update(t).set(transactions::size.eq(_size)).execute(conn)?;
+                    changed += 1;
+                }
+            }
+            last_id = batch[batch.len() - 1].id;
+            debug!("Backfilled sizes up to transaction {}", last_id);
+        }
+    }
+
     pub fn load_at_hash(conn: &PgConnection, _hash: &String) -> Option<Transaction> {
-        let sql = format!("select * from transactions where hash='{}'", _hash);
-        let mut _transactions: Vec<Transaction> = sql_query(sql).load(conn).unwrap();
-        /*
-        let mut _transactions = match transactions::table
-            .filter(hash.eq(_hash))
-            .limit(1)
-            .load::<Transaction>(conn) {
+        let mut _transactions: Vec<Transaction> =
+            match sql_query("select * from transactions where hash = $1 limit 1")
+            .bind::<Text, _>(_hash)
+            .load(conn) {
                 Ok(x) => x,
                 Err(y) => {
-                    error!("Error loading key block: {:?}", y);
+                    error!("Error loading transaction: {:?}", y);
                     return None;
                 },
             };
-         */
         Some(_transactions.pop()?)
     }
+
+    /*
+     * A page of the transactions which match filter, in chain order.
+     * With account, only those the account, contract, oracle, name or
+     * channel takes part in, and with role too, only those where it
+     * has that role.
+     */
+    pub fn load_list(conn: &PgConnection, account: &Option<String>, role: &Option<String>,
+                     filter: &TransactionFilter) ->
+        Result<Vec<Transaction>, Box<std::error::Error>>
+    {
+        let (after, direction) = match filter.descending {
+            true => ("<", "DESC"),
+            false => (">", "ASC"),
+        };
+        let sql = format!(
+            "SELECT t.* FROM transactions t \
+             WHERE ($1 IS NULL OR t.id IN \
+             (SELECT p.transaction_id FROM transaction_participants p \
+             WHERE p.account_id = $1 AND ($2 IS NULL OR p.role = $2))) \
+             AND ($3 IS NULL OR t.tx_type = $3) \
+             AND ($4 IS NULL OR t.block_height >= $4) \
+             AND ($5 IS NULL OR t.block_height <= $5) \
+             AND ($6 IS NULL OR t.mined_at >= $6) \
+             AND ($7 IS NULL OR t.mined_at <= $7) \
+             AND ($8 IS NULL OR t.valid = $8) \
+             AND ($9 IS NULL OR (t.block_height, t.micro_block_index, t.transaction_index, t.id) \
+             {0} ($9, $10, $11, $12)) \
+             ORDER BY t.block_height {1}, t.micro_block_index {1}, t.transaction_index {1}, t.id {1} \
+             LIMIT $13", after, direction);
+        let cursor = filter.cursor.as_ref();
+        Ok(sql_query(sql)
+            .bind::<Nullable<Text>, _>(account)
+            .bind::<Nullable<Text>, _>(role)
+            .bind::<Nullable<Text>, _>(&filter.tx_type)
+            .bind::<Nullable<BigInt>, _>(filter.from_height)
+            .bind::<Nullable<BigInt>, _>(filter.to_height)
+            .bind::<Nullable<Timestamp>, _>(filter.from_time.map(from_millis))
+            .bind::<Nullable<Timestamp>, _>(filter.to_time.map(from_millis))
+            .bind::<Nullable<Bool>, _>(filter.valid)
+            .bind::<Nullable<Integer>, _>(cursor.map(|x| x.block_height))
+            .bind::<Nullable<Integer>, _>(cursor.map(|x| x.micro_block_index))
+            .bind::<Nullable<Integer>, _>(cursor.map(|x| x.transaction_index))
+            .bind::<Nullable<Integer>, _>(cursor.map(|x| x.transaction_id))
+            .bind::<BigInt, _>(filter.limit)
+            .load(conn)?)
+    }
 }    
 
 #[derive(Serialize, Deserialize)]
 pub struct JsonTransaction {
     pub block_height: i32,
+    // "none" while the transaction is in the mempool, so not a MicroBlockHash
     pub block_hash: String,
-    pub hash: String,
+    pub hash: TxHash,
     pub signatures: Vec<String>,
     pub tx: serde_json::Value,
 }
@@ -397,8 +626,8 @@ pub struct JsonTransaction {
 impl JsonTransaction {
     pub fn from_transaction(t: &Transaction) -> JsonTransaction {
         let mut signatures: Vec<String> = vec![];
-        let _s = t.signatures.split(", ");
-        for s in _s {
+        let _s = t.signatures.split(|c| c == ' ' || c == ',');
+        for s in _s.filter(|x| !x.is_empty()) {
             signatures.push(String::from(s));
         }
         JsonTransaction {
@@ -411,23 +640,680 @@ impl JsonTransaction {
     }
 }
 
+/*
+ * A transaction as served from the DB, with what has happened to it
+ * so far.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonTransactionWithLifecycle {
+    #[serde(flatten)]
+    pub transaction: JsonTransaction,
+    pub lifecycle: Vec<JsonTransactionEvent>,
+}
+
+/*
+ * The account which sent a transaction, whose nonce it uses, has a
+ * different name depending on its type. Oracle transactions use the
+ * nonce of the account which registered the oracle, which has the
+ * same key. SENDER_ID is the same thing for SQL, with t being the
+ * transactions table.
+ */
+const SENDER_FIELDS: [&str; 6] =
+    ["sender_id", "account_id", "owner_id", "caller_id", "initiator_id", "from_id"];
+
+const SENDER_ID: &str = "COALESCE(t.tx->>'sender_id', t.tx->>'account_id', \
+                         t.tx->>'owner_id', t.tx->>'caller_id', t.tx->>'initiator_id', \
+                         t.tx->>'from_id', 'ak_' || substr(t.tx->>'oracle_id', 4), \
+                         t.tx->>'to_id')";
+
+pub fn sender_id(tx: &serde_json::Value) -> Option<String> {
+    for field in SENDER_FIELDS.iter() {
+        if let Some(x) = tx[*field].as_str() {
+            return Some(String::from(x));
+        }
+    }
+    if let Some(key) = tx["oracle_id"].as_str().and_then(|x| x.get(3..)) {
+        return Some(format!("ak_{}", key));
+    }
+    tx["to_id"].as_str().map(String::from)
+}
+
+const PARTICIPANT_PREFIXES: [&str; 5] = ["ak_", "ct_", "ok_", "nm_", "ch_"];
+
+fn is_participant(_id: &str) -> bool {
+    PARTICIPANT_PREFIXES.iter().any(|x| _id.starts_with(x))
+}
+
+/*
+ * Every account, contract, oracle, name and channel a transaction
+ * involves, as (id, role) pairs. The role is the name of the field the
+ * id is in without the _id, e.g. "sender" or "recipient", "delegate"
+ * for each of a channel's delegate_ids, and "pointer" for what a name
+ * points to.
+ */
+pub fn participants(tx: &serde_json::Value) -> Vec<(String, String)> {
+    let mut found = vec!();
+    let fields = match tx.as_object() {
+        Some(x) => x,
+        None => return found,
+    };
+    for (key, value) in fields {
+        if let Some(_id) = value.as_str() {
+            if is_participant(_id) {
+                found.push((String::from(_id), String::from(key.trim_end_matches("_id"))));
+            }
+        }
+        let items = match value.as_array() {
+            Some(x) => x,
+            None => continue,
+        };
+        for item in items {
+            if let Some(_id) = item.as_str() {
+                if is_participant(_id) {
+                    found.push((String::from(_id), String::from(key.trim_end_matches("_ids"))));
+                }
+            }
+            if let Some(_id) = item["id"].as_str() {
+                if is_participant(_id) {
+                    found.push((String::from(_id), String::from("pointer")));
+                }
+            }
+        }
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+/*
+ * Whether any transaction we hold involves this account, contract,
+ * oracle, name or channel.
+ */
+pub fn has_participated(conn: &PgConnection, _account_id: &String) ->
+    Result<bool, Box<std::error::Error>>
+{
+    Ok(select(exists(transaction_participants::table
+                     .filter(transaction_participants::account_id.eq(_account_id))))
+       .get_result(conn)?)
+}
+
+#[derive(Insertable)]
+#[table_name = "transaction_participants"]
+pub struct InsertableTransactionParticipant {
+    pub transaction_id: i32,
+    pub account_id: String,
+    pub role: String,
+}
+
+impl InsertableTransactionParticipant {
+    pub fn save_for_transaction(conn: &PgConnection, _transaction_id: i32,
+                                tx: &serde_json::Value) -> Result<(), Box<std::error::Error>> {
+        let rows: Vec<InsertableTransactionParticipant> = participants(tx).into_iter()
+            .map(|(_account_id, _role)| InsertableTransactionParticipant {
+                transaction_id: _transaction_id,
+                account_id: _account_id,
+                role: _role,
+            })
+            .collect();
+        diesel::insert_into(transaction_participants::table)
+            .values(&rows)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/*
+ * A transaction which is waiting in the mempool: not in a micro block,
+ * and not dropped.
+ */
+#[derive(QueryableByName)]
+pub struct PendingTransaction {
+    #[diesel(embed)]
+    pub transaction: Transaction,
+}
+
+impl PendingTransaction {
+    fn query(condition: &str) -> String {
+        format!(
+            "SELECT t.* FROM transactions t \
+             WHERE t.micro_block_id IS NULL AND t.valid AND {}", condition)
+    }
+
+    /*
+     * Longest waiting first, unless page is descending. Those from
+     * before we kept first_seen have waited longest of all. The
+     * cursor is bound as ${n} and the limit as ${n + 1}.
+     */
+    fn page_query(condition: &str, page: &ListPage, n: usize) -> String {
+        let (after, direction) = page.order(false);
+        format!(
+            "{0} AND (${1} IS NULL OR (COALESCE(t.first_seen, '-infinity'), t.id) {2} \
+             (SELECT COALESCE(c.first_seen, '-infinity'), c.id FROM transactions c \
+             WHERE c.id = ${1})) \
+             ORDER BY COALESCE(t.first_seen, '-infinity') {3}, t.id {3} LIMIT ${4}",
+            PendingTransaction::query(condition), n, after, direction, n + 1)
+    }
+
+    /*
+     * A filter which is None matches every transaction.
+     */
+    pub fn load_page(conn: &PgConnection, sender: &Option<String>,
+                     recipient: &Option<String>, _tx_type: &Option<String>,
+                     page: &ListPage) ->
+        Result<Vec<PendingTransaction>, Box<std::error::Error>>
+    {
+        let sql = PendingTransaction::page_query(&format!(
+            "($1 IS NULL OR {} = $1) \
+             AND ($2 IS NULL OR t.tx->>'recipient_id' = $2) \
+             AND ($3 IS NULL OR t.tx_type = $3)", SENDER_ID), page, 4);
+        let pending = sql_query(sql)
+            .bind::<Nullable<Text>, _>(sender)
+            .bind::<Nullable<Text>, _>(recipient)
+            .bind::<Nullable<Text>, _>(_tx_type)
+            .bind::<Nullable<Integer>, _>(page.cursor)
+            .bind::<BigInt, _>(page.limit)
+            .load(conn)?;
+        Ok(pending)
+    }
+
+    /*
+     * Everything the account has sent, in no particular order.
+     */
+    pub fn load_sent_by(conn: &PgConnection, account: &String) ->
+        Result<Vec<PendingTransaction>, Box<std::error::Error>>
+    {
+        let sql = PendingTransaction::query(&format!("{} = $1", SENDER_ID));
+        let pending = sql_query(sql)
+            .bind::<Text, _>(account)
+            .load(conn)?;
+        Ok(pending)
+    }
+
+    /*
+     * Those sent by the account, or to it.
+     */
+    pub fn load_for_account(conn: &PgConnection, account: &String, page: &ListPage) ->
+        Result<Vec<PendingTransaction>, Box<std::error::Error>>
+    {
+        let sql = PendingTransaction::page_query(&format!(
+            "({} = $1 OR t.tx->>'recipient_id' = $1)", SENDER_ID), page, 2);
+        let pending = sql_query(sql)
+            .bind::<Text, _>(account)
+            .bind::<Nullable<Integer>, _>(page.cursor)
+            .bind::<BigInt, _>(page.limit)
+            .load(conn)?;
+        Ok(pending)
+    }
+}
+
+/*
+ * The highest nonce an account has used in a mined transaction, kept
+ * up to date by the loader.
+ */
+#[derive(Queryable)]
+pub struct AccountNonce {
+    pub account: String,
+    pub nonce: i64,
+    pub updated_at: SystemTime,
+}
+
+impl AccountNonce {
+    /*
+     * 0 for an account which has never sent anything.
+     */
+    pub fn load(conn: &PgConnection, _account: &String) -> Result<i64, Box<std::error::Error>> {
+        let nonces = account_nonces::table
+            .filter(account_nonces::account.eq(_account))
+            .select(account_nonces::nonce)
+            .load::<i64>(conn)?;
+        Ok(*nonces.first().unwrap_or(&0))
+    }
+
+    /*
+     * Note a mined transaction's nonce, if it is higher than what we
+     * have.
+     */
+    pub fn record(conn: &PgConnection, tx: &serde_json::Value) -> Result<(), Box<std::error::Error>> {
+        let (_account, _nonce) = match (sender_id(tx), tx["nonce"].as_i64()) {
+            (Some(a), Some(n)) => (a, n),
+            _ => return Ok(()),
+        };
+        sql_query(
+            "INSERT INTO account_nonces (account, nonce) VALUES ($1, $2) \
+             ON CONFLICT (account) DO UPDATE SET \
+             nonce = GREATEST(account_nonces.nonce, EXCLUDED.nonce), updated_at = NOW()")
+            .bind::<Text, _>(&_account)
+            .bind::<BigInt, _>(_nonce)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /*
+     * Work the nonces of these accounts out again from the mined
+     * transactions, after some of theirs have been orphaned.
+     */
+    pub fn recompute(conn: &PgConnection, accounts: &Vec<String>) -> Result<(), Box<std::error::Error>> {
+        if accounts.is_empty() {
+            return Ok(());
+        }
+        sql_query(format!(
+            "UPDATE account_nonces a SET updated_at = NOW(), nonce = COALESCE( \
+             (SELECT MAX((t.tx->>'nonce')::BIGINT) FROM transactions t \
+             WHERE t.micro_block_id IS NOT NULL AND {} = a.account), 0) \
+             WHERE a.account = ANY($1)", SENDER_ID))
+            .bind::<Array<Text>, _>(accounts)
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/*
+ * waiting is how long, in milliseconds, since the transaction was
+ * first seen.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonPendingTransaction {
+    #[serde(flatten)]
+    pub transaction: JsonTransaction,
+    pub fee: i64,
+    pub first_seen: Option<i64>,
+    pub waiting: Option<i64>,
+}
+
+impl JsonPendingTransaction {
+    pub fn from_pending_transaction(p: &PendingTransaction) -> JsonPendingTransaction {
+        let first_seen = p.transaction.first_seen.as_ref().map(millis_since_epoch);
+        JsonPendingTransaction {
+            transaction: JsonTransaction::from_transaction(&p.transaction),
+            fee: p.transaction.fee,
+            first_seen,
+            waiting: first_seen.map(|t| millis_since_epoch(&SystemTime::now()) - t),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonPendingTransactionPage {
+    pub transactions: Vec<JsonPendingTransaction>,
+    pub next: Option<String>,
+}
+
+impl JsonPendingTransactionPage {
+    pub fn from_pending_transactions(pending: &[PendingTransaction], page: &ListPage) ->
+        JsonPendingTransactionPage
+    {
+        let ids: Vec<i32> = pending.iter().map(|x| x.transaction.id).collect();
+        JsonPendingTransactionPage {
+            transactions: pending.iter()
+                .map(JsonPendingTransaction::from_pending_transaction)
+                .collect(),
+            next: page.next(&ids),
+        }
+    }
+}
+
+/*
+ * How long transactions took to be mined, in milliseconds from when
+ * the mempool poller first saw them to the time of the micro block
+ * they went into, for transactions of one type paying fee_from to
+ * fee_to. Fee buckets double in size.
+ */
+#[derive(QueryableByName, Serialize, Deserialize)]
+pub struct InclusionLatency {
+    #[sql_type = "Text"]
+    pub tx_type: String,
+    #[sql_type = "BigInt"]
+    pub fee_from: i64,
+    #[sql_type = "BigInt"]
+    pub fee_to: i64,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+    #[sql_type = "Double"]
+    pub p50: f64,
+    #[sql_type = "Double"]
+    pub p90: f64,
+    #[sql_type = "Double"]
+    pub p99: f64,
+}
+
+impl InclusionLatency {
+    fn query(condition: &str) -> String {
+        format!(
+            "SELECT tx_type, POWER(2, bucket)::BIGINT AS fee_from, \
+             (POWER(2, bucket + 1) - 1)::BIGINT AS fee_to, COUNT(*) AS count, \
+             percentile_cont(0.5) WITHIN GROUP (ORDER BY latency) AS p50, \
+             percentile_cont(0.9) WITHIN GROUP (ORDER BY latency) AS p90, \
+             percentile_cont(0.99) WITHIN GROUP (ORDER BY latency) AS p99 \
+             FROM (SELECT t.tx_type, FLOOR(LOG(2, GREATEST(t.fee, 1)))::INT AS bucket, \
+             GREATEST(EXTRACT(EPOCH FROM t.mined_at - t.first_seen) * 1000, 0)::FLOAT8 AS latency \
+             FROM transactions t, micro_blocks m, key_blocks k \
+             WHERE t.micro_block_id = m.id AND m.key_block_id = k.id \
+             AND t.first_seen IS NOT NULL AND t.mined_at IS NOT NULL AND {}) s \
+             GROUP BY tx_type, bucket ORDER BY tx_type, bucket", condition)
+    }
+
+    /*
+     * For transactions mined from height from to height to inclusive.
+     */
+    pub fn for_heights(conn: &PgConnection, from: i64, to: i64) ->
+        Result<Vec<InclusionLatency>, Box<std::error::Error>>
+    {
+        let latencies = sql_query(InclusionLatency::query("k.height >= $1 AND k.height <= $2"))
+            .bind::<BigInt, _>(from)
+            .bind::<BigInt, _>(to)
+            .load(conn)?;
+        Ok(latencies)
+    }
+
+    /*
+     * For transactions mined between two times, in milliseconds.
+     */
+    pub fn for_times(conn: &PgConnection, from: i64, to: i64) ->
+        Result<Vec<InclusionLatency>, Box<std::error::Error>>
+    {
+        let latencies = sql_query(InclusionLatency::query(
+            "t.mined_at >= to_timestamp($1 / 1000.0) AT TIME ZONE 'UTC' \
+             AND t.mined_at <= to_timestamp($2 / 1000.0) AT TIME ZONE 'UTC'"))
+            .bind::<BigInt, _>(from)
+            .bind::<BigInt, _>(to)
+            .load(conn)?;
+        Ok(latencies)
+    }
+}
+
+// How far back we look to see what transactions are paying.
+pub const RECENT_GENERATIONS: i64 = 10;
+
+#[derive(QueryableByName)]
+struct LowestFee {
+    #[sql_type = "Text"]
+    tx_type: String,
+    #[sql_type = "BigInt"]
+    fee: i64,
+}
+
+/*
+ * The lowest fee paid by a mined transaction of each type in the last
+ * few generations.
+ */
+pub fn lowest_recent_fees(conn: &PgConnection, generations: i64) ->
+    Result<HashMap<String, i64>, Box<std::error::Error>>
+{
+    let fees: Vec<LowestFee> = sql_query(
+        "SELECT t.tx_type, MIN(t.fee) AS fee \
+         FROM transactions t, micro_blocks m, key_blocks k \
+         WHERE t.micro_block_id = m.id AND m.key_block_id = k.id \
+         AND k.height > (SELECT MAX(height) FROM key_blocks) - $1 \
+         GROUP BY t.tx_type")
+        .bind::<BigInt, _>(generations)
+        .load(conn)?;
+    Ok(fees.into_iter().map(|x| (x.tx_type, x.fee)).collect())
+}
+
+/*
+ * A pending transaction which isn't going anywhere, and why:
+ *
+ * nonce_used - the account has already mined a transaction with its nonce
+ * nonce_gap  - a lower nonce is missing, the first of which is missing_nonce
+ * low_fee    - it pays less than any transaction of its type mined
+ *              in the last RECENT_GENERATIONS generations did
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonStuckTransaction {
+    #[serde(flatten)]
+    pub transaction: JsonPendingTransaction,
+    pub reasons: Vec<String>,
+    pub missing_nonce: Option<i64>,
+    pub lowest_recent_fee: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonStuckTransactions {
+    pub account: String,
+    pub nonce: i64,
+    pub stuck: Vec<JsonStuckTransaction>,
+}
+
+impl JsonStuckTransactions {
+    /*
+     * Walk the account's pending transactions in nonce order. Each
+     * one must either follow the last mined nonce or another pending
+     * transaction, and once one doesn't, everything after it is
+     * blocked too.
+     */
+    pub fn for_account(conn: &PgConnection, account: &String) ->
+        Result<JsonStuckTransactions, Box<std::error::Error>>
+    {
+        let _nonce = AccountNonce::load(conn, account)?;
+        let fees = lowest_recent_fees(conn, RECENT_GENERATIONS)?;
+        let mut pending = PendingTransaction::load_sent_by(conn, account)?;
+        pending.sort_by_key(|p| p.transaction.tx["nonce"].as_i64());
+        let mut expected = _nonce + 1;
+        let mut missing_nonce: Option<i64> = None;
+        let mut stuck = vec!();
+        for p in &pending {
+            let mut reasons = vec!();
+            if let Some(n) = p.transaction.tx["nonce"].as_i64() {
+                if n <= _nonce {
+                    reasons.push(String::from("nonce_used"));
+                } else {
+                    if n > expected && missing_nonce.is_none() {
+                        missing_nonce = Some(expected);
+                    }
+                    if missing_nonce.is_some() {
+                        reasons.push(String::from("nonce_gap"));
+                    }
+                    expected = std::cmp::max(expected, n + 1);
+                }
+            }
+            let lowest_recent_fee = match p.transaction.tx["type"].as_str() {
+                Some(x) => fees.get(x).cloned(),
+                None => None,
+            };
+            if let Some(lowest) = lowest_recent_fee {
+                if p.transaction.fee < lowest {
+                    reasons.push(String::from("low_fee"));
+                }
+            }
+            if reasons.is_empty() {
+                continue;
+            }
+            let gap = reasons.iter().any(|x| x == "nonce_gap");
+            stuck.push(JsonStuckTransaction {
+                transaction: JsonPendingTransaction::from_pending_transaction(p),
+                reasons,
+                missing_nonce: if gap { missing_nonce } else { None },
+                lowest_recent_fee,
+            });
+        }
+        Ok(JsonStuckTransactions {
+            account: account.clone(),
+            nonce: _nonce,
+            stuck,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct JsonTransactionList {
     pub transactions: Vec<JsonTransaction>,
 }
 
+/*
+ * Where a transaction is in the chain, for paging through lists of
+ * them: its block height, the position of its micro block in the
+ * generation, its position in that micro block, and its id, which is
+ * all that orders transactions still in the mempool. Written as
+ * "height:micro_block_index:transaction_index:id".
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionCursor {
+    pub block_height: i32,
+    pub micro_block_index: i32,
+    pub transaction_index: i32,
+    pub transaction_id: i32,
+}
+
+impl TransactionCursor {
+    pub fn for_transaction(t: &Transaction) -> TransactionCursor {
+        TransactionCursor {
+            block_height: t.block_height,
+            micro_block_index: t.micro_block_index,
+            transaction_index: t.transaction_index,
+            transaction_id: t.id,
+        }
+    }
+}
+
+impl std::fmt::Display for TransactionCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.block_height, self.micro_block_index,
+               self.transaction_index, self.transaction_id)
+    }
+}
+
+impl FromStr for TransactionCursor {
+    type Err = Box<std::error::Error>;
+
+    fn from_str(s: &str) -> Result<TransactionCursor, Box<std::error::Error>> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 4 {
+            return Err(From::from(format!("Bad cursor {}", s)));
+        }
+        Ok(TransactionCursor {
+            block_height: parts[0].parse()?,
+            micro_block_index: parts[1].parse()?,
+            transaction_index: parts[2].parse()?,
+            transaction_id: parts[3].parse()?,
+        })
+    }
+}
+
+/*
+ * What a transaction list endpoint should return. Anything left out
+ * matches everything, and times are in milliseconds, of the micro
+ * block a transaction was mined in. Lists are in chain order, oldest
+ * first unless descending, and start after cursor.
+ */
+pub struct TransactionFilter {
+    pub tx_type: Option<String>,
+    pub from_height: Option<i64>,
+    pub to_height: Option<i64>,
+    pub from_time: Option<i64>,
+    pub to_time: Option<i64>,
+    pub valid: Option<bool>,
+    pub descending: bool,
+    pub cursor: Option<TransactionCursor>,
+    pub limit: i64,
+}
+
+impl TransactionFilter {
+    /*
+     * Only transactions mined between two times, in milliseconds, as
+     * well as whatever else the filter asks for. The generations they
+     * can be in are found first, from the key blocks at those times,
+     * so that the heights narrow the search.
+     */
+    pub fn between_times(self, conn: &PgConnection, from: i64, to: i64) -> TransactionFilter {
+        let from_block = KeyBlock::load_at_time(conn, from).map_or(0, |x| x.height);
+        let to_block = KeyBlock::load_at_time(conn, to).map_or(-1, |x| x.height);
+        self.within(from_block, to_block, from, to)
+    }
+
+    /*
+     * Narrows the heights and times to those which are also in these
+     * ranges. If they don't overlap, nothing matches.
+     */
+    fn within(self, from_block: i64, to_block: i64, from: i64, to: i64) -> TransactionFilter {
+        TransactionFilter {
+            from_height: Some(self.from_height.map_or(from_block, |x| x.max(from_block))),
+            to_height: Some(self.to_height.map_or(to_block, |x| x.min(to_block))),
+            from_time: Some(self.from_time.map_or(from, |x| x.max(from))),
+            to_time: Some(self.to_time.map_or(to, |x| x.min(to))),
+            ..self
+        }
+    }
+}
+
+/*
+ * A page of one of the lists which aren't of mined transactions, the
+ * reorgs, proofs of fraud and mempool, which are paged through like
+ * the transaction lists. cursor is the id of the last item of the
+ * page before. Each list has its own order, which descending
+ * overrides when it is given.
+ */
+pub struct ListPage {
+    pub cursor: Option<i32>,
+    pub descending: Option<bool>,
+    pub limit: i64,
+}
+
+impl ListPage {
+    /*
+     * How to compare with the cursor, and which way to order, when the
+     * list is descending by default or not.
+     */
+    fn order(&self, descending: bool) -> (&'static str, &'static str) {
+        if self.descending.unwrap_or(descending) {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        }
+    }
+
+    /*
+     * The cursor for the page after the one with these ids, None if it
+     * was the last.
+     */
+    pub fn next(&self, ids: &[i32]) -> Option<String> {
+        match ids.last() {
+            Some(x) if ids.len() as i64 == self.limit => Some(x.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/*
+ * next is the cursor to ask for the page after this one with, and is
+ * null when there is nothing more.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonTransactionPage {
+    pub transactions: Vec<JsonTransaction>,
+    pub next: Option<String>,
+}
+
+impl JsonTransactionPage {
+    pub fn from_transactions(_transactions: &Vec<Transaction>, limit: i64) -> JsonTransactionPage {
+        let next = match _transactions.last() {
+            Some(x) if _transactions.len() as i64 == limit =>
+                Some(TransactionCursor::for_transaction(x).to_string()),
+            _ => None,
+        };
+        JsonTransactionPage {
+            transactions: _transactions.iter().map(JsonTransaction::from_transaction).collect(),
+            next,
+        }
+    }
+}
+
 #[derive(Insertable)]
 #[table_name = "transactions"]
 pub struct InsertableTransaction {
     pub micro_block_id: Option<i32>,
     pub block_height: i32,
     pub block_hash: String,
-    pub hash: String,
+    pub hash: TxHash,
     pub signatures: String,
     pub tx_type: String,
     pub fee: i64,
     pub size: i32,
     pub tx: serde_json::Value,
+    pub first_seen: Option<SystemTime>,
+    pub mined_at: Option<SystemTime>,
+    pub micro_block_index: i32,
+    pub transaction_index: i32,
 }
 
 impl InsertableTransaction {
@@ -436,6 +1322,7 @@ impl InsertableTransaction {
         use diesel::RunQueryDsl;
         use schema::transactions::dsl::*;
         let generated_ids: Vec<i32> = insert_into(transactions).values(self).returning(id).get_results(&*conn)?;
+        InsertableTransactionParticipant::save_for_transaction(conn, generated_ids[0], &self.tx)?;
         Ok(generated_ids[0])
     }
 
@@ -459,8 +1346,834 @@ impl InsertableTransaction {
             signatures,
             tx_type,
             fee: jt.tx["fee"].as_i64().unwrap(),
-            size: jt.tx.to_string().len() as i32,
+            size: serialized_size(jt),
             tx: serde_json::from_str(&jt.tx.to_string()).unwrap(),
+            first_seen: None,
+            mined_at: None,
+            micro_block_index: -1,
+            transaction_index: -1,
+        })
+    }
+}
+
+/*
+ * The size of the signed transaction as the node serializes it, which
+ * is what its fee is worked out from. 0 if we can't serialize it.
+ */
+pub fn serialized_size(jt: &JsonTransaction) -> i32 {
+    match serialization::signed_tx_size(jt) {
+        Ok(x) => x as i32,
+        Err(e) => {
+            warn!("Couldn't work out size of transaction {}: {}", jt.hash, e);
+            0
+        },
+    }
+}
+
+/*
+ * When a fork replaces blocks we already hold, they and their
+ * transactions are copied to the orphaned_* tables before being
+ * removed, so that we can still tell people where a transaction used
+ * to be.
+ *
+ * Copy the micro blocks with these ids and their transactions to the
+ * orphan tables, then delete them. Returns the number of transactions
+ * orphaned.
+ */
+pub fn orphan_micro_blocks(conn: &PgConnection, ids: &Vec<i32>) ->
+    Result<usize, Box<std::error::Error>>
+{
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    sql_query(
+        "INSERT INTO orphaned_micro_blocks \
+         (key_block_hash, height, hash, pof_hash, prev_hash, prev_key_hash, \
+         signature, state_hash, txs_hash, version) \
+         SELECT k.hash, k.height, m.hash, m.pof_hash, m.prev_hash, m.prev_key_hash, \
+         m.signature, m.state_hash, m.txs_hash, m.version \
+         FROM micro_blocks m, key_blocks k \
+         WHERE m.key_block_id = k.id AND m.id = ANY($1)")
+        .bind::<Array<Integer>, _>(ids)
+        .execute(conn)?;
+    sql_query(
+        "INSERT INTO transaction_events \
+         (transaction_hash, event, block_height, micro_block_hash) \
+         SELECT t.hash, 'reorged_out', t.block_height, m.hash \
+         FROM transactions t, micro_blocks m \
+         WHERE t.micro_block_id = m.id AND m.id = ANY($1)")
+        .bind::<Array<Integer>, _>(ids)
+        .execute(conn)?;
+    sql_query(
+        "UPDATE submissions s SET status = 'pending', block_height = NULL, \
+         micro_block_hash = NULL, updated_at = NOW() \
+         FROM transactions t WHERE s.hash = t.hash AND t.micro_block_id = ANY($1)")
+        .bind::<Array<Integer>, _>(ids)
+        .execute(conn)?;
+    let count = sql_query(
+        "INSERT INTO orphaned_transactions \
+         (micro_block_hash, key_block_hash, block_height, hash, signatures, \
+         tx_type, tx, fee, size) \
+         SELECT m.hash, k.hash, t.block_height, t.hash, t.signatures, \
+         t.tx_type, t.tx, t.fee, t.size \
+         FROM transactions t, micro_blocks m, key_blocks k \
+         WHERE t.micro_block_id = m.id AND m.key_block_id = k.id AND m.id = ANY($1)")
+        .bind::<Array<Integer>, _>(ids)
+        .execute(conn)?;
+    let mut senders: Vec<String> = transactions::table
+        .filter(transactions::micro_block_id.eq_any(ids))
+        .select(transactions::tx)
+        .load::<serde_json::Value>(conn)?
+        .iter()
+        .filter_map(sender_id)
+        .collect();
+    senders.sort();
+    senders.dedup();
+    diesel::delete(micro_blocks::table.filter(micro_blocks::id.eq_any(ids))).execute(conn)?;
+    AccountNonce::recompute(conn, &senders)?;
+    debug!("Orphaned {} micro blocks with {} transactions", ids.len(), count);
+    Ok(count)
+}
+
+/*
+ * Copy the key block at this height, its micro blocks and their
+ * transactions to the orphan tables, then delete them. Returns the
+ * number of transactions orphaned.
+ */
+pub fn orphan_generation(conn: &PgConnection, _height: i64) ->
+    Result<usize, Box<std::error::Error>>
+{
+    sql_query(
+        "INSERT INTO orphaned_key_blocks \
+         (hash, height, miner, beneficiary, nonce, pow, prev_hash, prev_key_hash, \
+         state_hash, target, time_, version) \
+         SELECT hash, height, miner, beneficiary, nonce, pow, prev_hash, prev_key_hash, \
+         state_hash, target, time_, version \
+         FROM key_blocks WHERE height = $1")
+        .bind::<BigInt, _>(_height)
+        .execute(conn)?;
+    let ids: Vec<i32> = micro_blocks::table
+        .inner_join(key_blocks::table)
+        .filter(key_blocks::height.eq(_height))
+        .select(micro_blocks::id)
+        .load(conn)?;
+    let count = orphan_micro_blocks(conn, &ids)?;
+    diesel::delete(key_blocks::table.filter(key_blocks::height.eq(_height))).execute(conn)?;
+    Ok(count)
+}
+
+#[derive(Queryable, QueryableByName)]
+#[table_name = "orphaned_transactions"]
+pub struct OrphanedTransaction {
+    pub id: i32,
+    pub micro_block_hash: String,
+    pub key_block_hash: String,
+    pub block_height: i32,
+    pub hash: String,
+    pub signatures: String,
+    pub tx_type: String,
+    pub tx: serde_json::Value,
+    pub fee: i64,
+    pub size: i32,
+    pub orphaned_at: SystemTime,
+}
+
+impl OrphanedTransaction {
+    pub fn load_for_hash(conn: &PgConnection, _hash: &String) ->
+        Result<Vec<OrphanedTransaction>, Box<std::error::Error>>
+    {
+        let orphans = orphaned_transactions::table
+            .filter(orphaned_transactions::hash.eq(_hash))
+            .order(orphaned_transactions::orphaned_at.asc())
+            .load::<OrphanedTransaction>(conn)?;
+        Ok(orphans)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonOrphanedTransaction {
+    pub block_height: i32,
+    pub key_block_hash: String,
+    pub micro_block_hash: String,
+    pub orphaned_at: i64,
+}
+
+impl JsonOrphanedTransaction {
+    pub fn from_orphaned_transaction(t: &OrphanedTransaction) -> JsonOrphanedTransaction {
+        JsonOrphanedTransaction {
+            block_height: t.block_height,
+            key_block_hash: t.key_block_hash.clone(),
+            micro_block_hash: t.micro_block_hash.clone(),
+            orphaned_at: millis_since_epoch(&t.orphaned_at),
+        }
+    }
+}
+
+/*
+ * The blocks a transaction was in before they were forked away, and
+ * the transaction as we have it now, if it has been seen again.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonTransactionOrphans {
+    pub orphaned: Vec<JsonOrphanedTransaction>,
+    pub current: Option<JsonTransaction>,
+}
+
+/*
+ * Each thing that happens to a transaction is recorded, so that we can
+ * say when it was first seen, when it was mined, and whether it has
+ * since been dropped from the mempool or forked out of the chain:
+ *
+ * seen_in_mempool - it turned up in the node's mempool
+ * mined           - it was found in a micro block for the first time
+ * dropped         - it left the mempool without being mined
+ * reorged_out     - the micro block it was in was lost to a fork
+ * re_mined        - it was found in a micro block again after that
+ */
+#[derive(Queryable)]
+pub struct TransactionEvent {
+    pub id: i32,
+    pub transaction_hash: String,
+    pub event: String,
+    pub block_height: Option<i32>,
+    pub micro_block_hash: Option<String>,
+    pub happened_at: SystemTime,
+}
+
+impl TransactionEvent {
+    /*
+     * Oldest first.
+     */
+    pub fn load_for_hash(conn: &PgConnection, _hash: &String) ->
+        Result<Vec<TransactionEvent>, Box<std::error::Error>>
+    {
+        let events = transaction_events::table
+            .filter(transaction_events::transaction_hash.eq(_hash))
+            .order(transaction_events::id.asc())
+            .load::<TransactionEvent>(conn)?;
+        Ok(events)
+    }
+
+    /*
+     * When the transaction was first seen in the mempool, if it ever
+     * was.
+     */
+    pub fn first_seen(conn: &PgConnection, _hash: &String) ->
+        Result<Option<SystemTime>, Box<std::error::Error>>
+    {
+        let seen = transaction_events::table
+            .filter(transaction_events::transaction_hash.eq(_hash))
+            .filter(transaction_events::event.eq("seen_in_mempool"))
+            .select(transaction_events::happened_at)
+            .order(transaction_events::happened_at.asc())
+            .limit(1)
+            .load::<SystemTime>(conn)?;
+        Ok(seen.first().cloned())
+    }
+
+    /*
+     * Whether the transaction has been mined before, i.e. finding it
+     * in a block now makes it re_mined rather than mined.
+     */
+    pub fn was_mined(conn: &PgConnection, _hash: &String) -> Result<bool, Box<std::error::Error>> {
+        let mined: bool = select(exists(
+            transaction_events::table
+                .filter(transaction_events::transaction_hash.eq(_hash))
+                .filter(transaction_events::event.eq_any(vec!("mined", "re_mined")))))
+            .get_result(conn)?;
+        Ok(mined)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "transaction_events"]
+pub struct InsertableTransactionEvent {
+    pub transaction_hash: String,
+    pub event: String,
+    pub block_height: Option<i32>,
+    pub micro_block_hash: Option<String>,
+}
+
+impl InsertableTransactionEvent {
+    pub fn new(_hash: &String, event: &str) -> InsertableTransactionEvent {
+        InsertableTransactionEvent {
+            transaction_hash: _hash.clone(),
+            event: String::from(event),
+            block_height: None,
+            micro_block_hash: None,
+        }
+    }
+
+    pub fn save(&self, conn: &PgConnection) -> Result<(), Box<std::error::Error>> {
+        use diesel::dsl::insert_into;
+        insert_into(transaction_events::table).values(self).execute(&*conn)?;
+        debug!("Transaction {} {}", self.transaction_hash, self.event);
+        Submission::follow(conn, self)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonTransactionEvent {
+    pub event: String,
+    pub block_height: Option<i32>,
+    pub micro_block_hash: Option<String>,
+    pub happened_at: i64,
+}
+
+impl JsonTransactionEvent {
+    pub fn from_transaction_event(e: &TransactionEvent) -> JsonTransactionEvent {
+        JsonTransactionEvent {
+            event: e.event.clone(),
+            block_height: e.block_height,
+            micro_block_hash: e.micro_block_hash.clone(),
+            happened_at: millis_since_epoch(&e.happened_at),
+        }
+    }
+}
+
+/*
+ * A transaction which was posted to the node through the middleware,
+ * and who posted it. status is "pending" until it is mined, when
+ * block_height and micro_block_hash say where, or "dropped" if it
+ * leaves the mempool without being mined. It goes back to "pending" if
+ * its block is lost to a fork.
+ */
+#[derive(Queryable)]
+pub struct Submission {
+    pub id: i32,
+    pub hash: String,
+    pub remote_addr: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+    pub submitted_at: SystemTime,
+    pub status: String,
+    pub block_height: Option<i32>,
+    pub micro_block_hash: Option<String>,
+    pub updated_at: SystemTime,
+}
+
+impl Submission {
+    pub fn load_for_hash(conn: &PgConnection, _hash: &String) ->
+        Result<Option<Submission>, Box<std::error::Error>>
+    {
+        let mut found = submissions::table
+            .filter(submissions::hash.eq(_hash))
+            .load::<Submission>(conn)?;
+        Ok(found.pop())
+    }
+
+    /*
+     * Keep the submission of this transaction, if there is one, up to
+     * date with what has just happened to it.
+     */
+    pub fn follow(conn: &PgConnection, event: &InsertableTransactionEvent) ->
+        Result<(), Box<std::error::Error>>
+    {
+        let _status = match event.event.as_str() {
+            "mined" | "re_mined" => "mined",
+            "dropped" => "dropped",
+            _ => "pending",
+        };
+        diesel::update(submissions::table.filter(submissions::hash.eq(&event.transaction_hash)))
+            .set((submissions::status.eq(_status),
+                  submissions::block_height.eq(event.block_height),
+                  submissions::micro_block_hash.eq(event.micro_block_hash.clone()),
+                  submissions::updated_at.eq(SystemTime::now())))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "submissions"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct InsertableSubmission {
+    pub hash: String,
+    pub remote_addr: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+    pub submitted_at: SystemTime,
+    pub status: String,
+}
+
+impl InsertableSubmission {
+    /*
+     * A transaction posted again is tracked from the latest time.
+     */
+    pub fn save(&self, conn: &PgConnection) -> Result<(), Box<std::error::Error>> {
+        use diesel::dsl::insert_into;
+        insert_into(submissions::table)
+            .values(self)
+            .on_conflict(submissions::hash)
+            .do_update()
+            .set(self)
+            .execute(conn)?;
+        debug!("Transaction {} submitted", self.hash);
+        Ok(())
+    }
+}
+
+/*
+ * confirmations is how many key blocks have been added on top of the
+ * one a mined transaction is in.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct JsonSubmission {
+    pub hash: String,
+    pub status: String,
+    pub submitted_at: i64,
+    pub updated_at: i64,
+    pub block_height: Option<i32>,
+    pub micro_block_hash: Option<String>,
+    pub confirmations: Option<i64>,
+}
+
+impl JsonSubmission {
+    pub fn from_submission(s: &Submission, top_height: i64) -> JsonSubmission {
+        JsonSubmission {
+            hash: s.hash.clone(),
+            status: s.status.clone(),
+            submitted_at: millis_since_epoch(&s.submitted_at),
+            updated_at: millis_since_epoch(&s.updated_at),
+            block_height: s.block_height,
+            micro_block_hash: s.micro_block_hash.clone(),
+            confirmations: s.block_height.map(|h| std::cmp::max(top_height - h as i64, 0)),
+        }
+    }
+}
+
+#[derive(Queryable, QueryableByName)]
+#[table_name = "reorgs"]
+pub struct Reorg {
+    pub id: i32,
+    pub detected_at: SystemTime,
+    pub from_height: i64,
+    pub to_height: i64,
+    pub old_hashes: Vec<String>,
+    pub new_hashes: Vec<String>,
+    pub transactions_affected: i32,
+    pub kind: String,
+}
+
+impl Reorg {
+    /*
+     * Most recent first, unless page is ascending.
+     */
+    pub fn load_page(conn: &PgConnection, page: &ListPage) ->
+        Result<Vec<Reorg>, Box<std::error::Error>>
+    {
+        let (after, direction) = page.order(true);
+        let sql = format!(
+            "SELECT r.* FROM reorgs r WHERE ($1 IS NULL OR r.id {0} $1) \
+             ORDER BY r.id {1} LIMIT $2", after, direction);
+        Ok(sql_query(sql)
+            .bind::<Nullable<Integer>, _>(page.cursor)
+            .bind::<BigInt, _>(page.limit)
+            .load(conn)?)
+    }
+}
+
+/*
+ * A record of a fork we detected: the block hashes we held for
+ * from_height..to_height, what replaced them, and how many
+ * transactions were orphaned as a result. kind is "key" when key
+ * blocks were replaced, and "micro" when micro blocks within one
+ * generation were.
+ */
+#[derive(Insertable)]
+#[table_name = "reorgs"]
+pub struct InsertableReorg {
+    pub from_height: i64,
+    pub to_height: i64,
+    pub old_hashes: Vec<String>,
+    pub new_hashes: Vec<String>,
+    pub transactions_affected: i32,
+    pub kind: String,
+}
+
+impl InsertableReorg {
+    pub fn save(&self, conn: &PgConnection) -> Result<i32, Box<std::error::Error>> {
+        use diesel::dsl::insert_into;
+        use schema::reorgs::dsl::*;
+        let generated_ids: Vec<i32> = insert_into(reorgs).values(self).returning(id).get_results(&*conn)?;
+        info!("Recorded reorg of heights {} to {}", self.from_height, self.to_height);
+        Ok(generated_ids[0])
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonReorg {
+    pub id: i32,
+    pub kind: String,
+    pub detected_at: i64,
+    pub from_height: i64,
+    pub to_height: i64,
+    pub old_hashes: Vec<String>,
+    pub new_hashes: Vec<String>,
+    pub transactions_affected: i32,
+}
+
+impl JsonReorg {
+    pub fn from_reorg(r: &Reorg) -> JsonReorg {
+        JsonReorg {
+            id: r.id,
+            kind: r.kind.clone(),
+            detected_at: millis_since_epoch(&r.detected_at),
+            from_height: r.from_height,
+            to_height: r.to_height,
+            old_hashes: r.old_hashes.clone(),
+            new_hashes: r.new_hashes.clone(),
+            transactions_affected: r.transactions_affected,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonReorgPage {
+    pub reorgs: Vec<JsonReorg>,
+    pub next: Option<String>,
+}
+
+impl JsonReorgPage {
+    pub fn from_reorgs(_reorgs: &[Reorg], page: &ListPage) -> JsonReorgPage {
+        let ids: Vec<i32> = _reorgs.iter().map(|x| x.id).collect();
+        JsonReorgPage {
+            reorgs: _reorgs.iter().map(JsonReorg::from_reorg).collect(),
+            next: page.next(&ids),
+        }
+    }
+}
+
+#[derive(Queryable, QueryableByName)]
+#[table_name = "proofs_of_fraud"]
+pub struct ProofOfFraud {
+    pub id: i32,
+    pub micro_block_hash: MicroBlockHash,
+    pub pof_hash: String,
+    pub height: i64,
+    pub reporter: AccountId,
+    pub offender: Option<AccountId>,
+    pub detected_at: SystemTime,
+}
+
+impl ProofOfFraud {
+    /*
+     * Highest first, unless page is ascending.
+     */
+    pub fn load_page(conn: &PgConnection, page: &ListPage) ->
+        Result<Vec<ProofOfFraud>, Box<std::error::Error>>
+    {
+        let (after, direction) = page.order(true);
+        let sql = format!(
+            "SELECT p.* FROM proofs_of_fraud p \
+             WHERE ($1 IS NULL OR (p.height, p.id) {0} \
+             (SELECT c.height, c.id FROM proofs_of_fraud c WHERE c.id = $1)) \
+             ORDER BY p.height {1}, p.id {1} LIMIT $2", after, direction);
+        Ok(sql_query(sql)
+            .bind::<Nullable<Integer>, _>(page.cursor)
+            .bind::<BigInt, _>(page.limit)
+            .load(conn)?)
+    }
+}
+
+/*
+ * A micro block carrying a proof of fraud, which the leader of its
+ * generation (the reporter) includes against the leader of the
+ * generation before (the offender), who signed conflicting micro
+ * blocks.
+ */
+#[derive(Insertable)]
+#[table_name = "proofs_of_fraud"]
+pub struct InsertableProofOfFraud {
+    pub micro_block_hash: MicroBlockHash,
+    pub pof_hash: String,
+    pub height: i64,
+    pub reporter: AccountId,
+    pub offender: Option<AccountId>,
+}
+
+impl InsertableProofOfFraud {
+    /*
+     * The node reports "no_fraud" in pof_hash for ordinary micro
+     * blocks.
+     */
+    pub fn from_micro_block(mb: &InsertableMicroBlock, kb: &JsonKeyBlock,
+                            offender: Option<AccountId>) -> Option<InsertableProofOfFraud> {
+        if mb.pof_hash.is_empty() || mb.pof_hash == "no_fraud" {
+            return None;
+        }
+        Some(InsertableProofOfFraud {
+            micro_block_hash: mb.hash.clone(),
+            pof_hash: mb.pof_hash.clone(),
+            height: kb.height,
+            reporter: kb.miner.clone(),
+            offender,
         })
     }
+
+    pub fn save(&self, conn: &PgConnection) -> Result<(), Box<std::error::Error>> {
+        use diesel::dsl::insert_into;
+        use schema::proofs_of_fraud::dsl::*;
+        insert_into(proofs_of_fraud).values(self).on_conflict_do_nothing().execute(&*conn)?;
+        info!("Proof of fraud in micro block {} against {:?}", self.micro_block_hash, self.offender);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonProofOfFraud {
+    pub micro_block_hash: MicroBlockHash,
+    pub pof_hash: String,
+    pub height: i64,
+    pub reporter: AccountId,
+    pub offender: Option<AccountId>,
+    pub detected_at: i64,
+}
+
+impl JsonProofOfFraud {
+    pub fn from_proof_of_fraud(p: &ProofOfFraud) -> JsonProofOfFraud {
+        JsonProofOfFraud {
+            micro_block_hash: p.micro_block_hash.clone(),
+            pof_hash: p.pof_hash.clone(),
+            height: p.height,
+            reporter: p.reporter.clone(),
+            offender: p.offender.clone(),
+            detected_at: millis_since_epoch(&p.detected_at),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonProofOfFraudPage {
+    pub proofs_of_fraud: Vec<JsonProofOfFraud>,
+    pub next: Option<String>,
+}
+
+impl JsonProofOfFraudPage {
+    pub fn from_proofs_of_fraud(proofs: &[ProofOfFraud], page: &ListPage) ->
+        JsonProofOfFraudPage
+    {
+        let ids: Vec<i32> = proofs.iter().map(|x| x.id).collect();
+        JsonProofOfFraudPage {
+            proofs_of_fraud: proofs.iter().map(JsonProofOfFraud::from_proof_of_fraud).collect(),
+            next: page.next(&ids),
+        }
+    }
+}
+
+// Block times come in as milliseconds.
+pub fn from_millis(t: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(t as u64)
+}
+
+/*
+ * Whether a time in milliseconds from a request is one we can turn
+ * into a timestamp, from 1970 to the end of the year 9999.
+ */
+pub fn is_valid_millis(t: i64) -> bool {
+    t >= 0 && t <= 253_402_300_799_999
+}
+
+// Timestamps go out as milliseconds, like key block times.
+pub fn millis_since_epoch(t: &SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() * 1000 + d.subsec_millis() as u64) as i64,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = TransactionCursor {
+            block_height: 1200,
+            micro_block_index: 3,
+            transaction_index: 0,
+            transaction_id: 98765,
+        };
+        assert_eq!(cursor.to_string(), "1200:3:0:98765");
+        assert_eq!("1200:3:0:98765".parse::<TransactionCursor>().unwrap(), cursor);
+        // the mempool, which is ordered by id alone
+        assert_eq!("-1:-1:-1:5".parse::<TransactionCursor>().unwrap().transaction_id, 5);
+    }
+
+    #[test]
+    fn cursor_rejects_malformed() {
+        for x in ["", "1:2:3", "1:2:3:4:5", "a:b:c:d", "1:2:3:", "1::3:4", "1:2:3:99999999999",
+                  "1:2:3:4' OR '1'='1"].iter() {
+            assert!(x.parse::<TransactionCursor>().is_err(), "{} parsed", x);
+        }
+    }
+
+    #[test]
+    fn checks_times() {
+        for x in [0, 1546300800000, 253_402_300_799_999].iter() {
+            assert!(is_valid_millis(*x), "{}", x);
+            assert_eq!(millis_since_epoch(&from_millis(*x)), *x);
+        }
+        for x in [-1, 253_402_300_800_000, std::i64::MIN, std::i64::MAX].iter() {
+            assert!(!is_valid_millis(*x), "{}", x);
+        }
+    }
+
+    #[test]
+    fn narrows_filters() {
+        let filter = |from_height, to_height, from_time, to_time| TransactionFilter {
+            tx_type: None,
+            from_height: from_height,
+            to_height: to_height,
+            from_time: from_time,
+            to_time: to_time,
+            valid: None,
+            descending: false,
+            cursor: None,
+            limit: 100,
+        };
+        let narrowed = filter(None, None, None, None).within(10, 20, 1000, 2000);
+        assert_eq!((narrowed.from_height, narrowed.to_height), (Some(10), Some(20)));
+        assert_eq!((narrowed.from_time, narrowed.to_time), (Some(1000), Some(2000)));
+        let narrowed = filter(Some(15), Some(30), Some(500), Some(1500))
+            .within(10, 20, 1000, 2000);
+        assert_eq!((narrowed.from_height, narrowed.to_height), (Some(15), Some(20)));
+        assert_eq!((narrowed.from_time, narrowed.to_time), (Some(1000), Some(1500)));
+        // ranges which don't overlap match nothing, rather than the times'
+        let narrowed = filter(Some(30), Some(40), None, None).within(10, 20, 1000, 2000);
+        assert_eq!((narrowed.from_height, narrowed.to_height), (Some(30), Some(20)));
+    }
+
+    #[test]
+    fn finds_participants() {
+        let tx: serde_json::Value = serde_json::from_str(r#"{
+            "type": "ChannelCreateTx",
+            "initiator_id": "ak_1",
+            "responder_id": "ak_2",
+            "delegate_ids": ["ak_3", "ak_1"],
+            "state_hash": "st_4",
+            "fee": 1
+        }"#).unwrap();
+        assert_eq!(participants(&tx), vec!(
+            (String::from("ak_1"), String::from("delegate")),
+            (String::from("ak_1"), String::from("initiator")),
+            (String::from("ak_2"), String::from("responder")),
+            (String::from("ak_3"), String::from("delegate")),
+        ));
+        let tx: serde_json::Value = serde_json::from_str(r#"{
+            "type": "NameUpdateTx",
+            "account_id": "ak_1",
+            "name_id": "nm_2",
+            "pointers": [{"key": "account_pubkey", "id": "ak_3"},
+                         {"key": "oracle_pubkey", "id": "ok_4"}]
+        }"#).unwrap();
+        assert_eq!(participants(&tx), vec!(
+            (String::from("ak_1"), String::from("account")),
+            (String::from("ak_3"), String::from("pointer")),
+            (String::from("nm_2"), String::from("name")),
+            (String::from("ok_4"), String::from("pointer")),
+        ));
+        assert!(participants(&serde_json::Value::Null).is_empty());
+    }
+
+    #[test]
+    fn finds_senders() {
+        let tx: serde_json::Value =
+            serde_json::from_str(r#"{"oracle_id": "ok_2a1", "nonce": 1}"#).unwrap();
+        assert_eq!(sender_id(&tx), Some(String::from("ak_2a1")));
+        let tx: serde_json::Value =
+            serde_json::from_str(r#"{"caller_id": "ak_3", "contract_id": "ct_4"}"#).unwrap();
+        assert_eq!(sender_id(&tx), Some(String::from("ak_3")));
+        assert_eq!(sender_id(&serde_json::Value::Null), None);
+    }
+
+    // what would have got into SQL built with format!, down to the
+    // characters LIKE and string escapes treat specially
+    const HOSTILE: [&str; 4] = [
+        "ak_'; DROP TABLE transactions; --",
+        "ak_\\'); DELETE FROM key_blocks; --",
+        "ak_' OR '1'='1",
+        "ak_%",
+    ];
+
+    fn save_pending(conn: &PgConnection, sender: &str, n: u8) ->
+        Result<i32, Box<std::error::Error>>
+    {
+        let mut tx = serde_json::Map::new();
+        for (key, value) in [("type", "SpendTx"), ("sender_id", sender),
+                             ("recipient_id", "ak_2")].iter() {
+            tx.insert(String::from(*key), serde_json::Value::from(*value));
+        }
+        InsertableTransaction {
+            micro_block_id: None,
+            block_height: -1,
+            block_hash: String::from("none"),
+            hash: ::encoding::encode("th", &[n; 32]).parse()?,
+            signatures: String::new(),
+            tx_type: String::from("SpendTx"),
+            fee: 1,
+            size: 0,
+            tx: serde_json::Value::Object(tx),
+            first_seen: None,
+            mined_at: None,
+            micro_block_index: -1,
+            transaction_index: -1,
+        }.save(conn)
+    }
+
+    /*
+     * Needs a migrated DB at DATABASE_URL, so run it with
+     * cargo test -- --ignored. Nothing it saves is committed.
+     */
+    #[test]
+    #[ignore]
+    fn binds_hostile_values() {
+        // its checksum is good, so it gets past the route guards, and
+        // what it encodes is SQL
+        let checked = ::encoding::encode("ak", b"'; DROP TABLE transactions; --");
+        assert!(checked.parse::<AccountId>().is_ok());
+        let mut senders: Vec<&str> = HOSTILE.to_vec();
+        senders.push(&checked);
+        let pool = ::epoch::establish_connection(1);
+        let conn = pool.get().unwrap();
+        conn.test_transaction::<_, Box<std::error::Error>, _>(|| {
+            let mut saved = vec!();
+            for (n, sender) in senders.iter().enumerate() {
+                saved.push(save_pending(&conn, sender, n as u8 + 1)?);
+            }
+            let page = ListPage { cursor: None, descending: None, limit: 1000 };
+            let filter = |_tx_type: &str| TransactionFilter {
+                tx_type: Some(String::from(_tx_type)),
+                from_height: None,
+                to_height: None,
+                from_time: None,
+                to_time: None,
+                valid: None,
+                descending: false,
+                cursor: None,
+                limit: 1000,
+            };
+            let ids = |x: Vec<PendingTransaction>| -> Vec<i32> {
+                x.iter().map(|y| y.transaction.id).collect()
+            };
+            // each matches its own transaction and nothing else
+            for (sender, _id) in senders.iter().zip(saved.iter()) {
+                let sender = Some(String::from(*sender));
+                let sent = PendingTransaction::load_sent_by(&conn, sender.as_ref().unwrap())?;
+                assert_eq!(ids(sent), vec!(*_id));
+                let sent = PendingTransaction::load_page(&conn, &sender, &None, &None, &page)?;
+                assert_eq!(ids(sent), vec!(*_id));
+                let sent = PendingTransaction::load_page(&conn, &None, &sender, &sender, &page)?;
+                assert!(sent.is_empty());
+                let listed = Transaction::load_list(
+                    &conn, &sender, &Some(String::from("sender")), &filter("SpendTx"))?;
+                assert_eq!(listed.iter().map(|x| x.id).collect::<Vec<i32>>(), vec!(*_id));
+                assert!(Transaction::load_list(&conn, &sender, &sender, &filter("SpendTx"))?
+                        .is_empty());
+                let by_type = filter(sender.as_ref().unwrap());
+                assert!(Transaction::load_list(&conn, &None, &None, &by_type)?.is_empty());
+            }
+            Ok(())
+        });
+    }
 }
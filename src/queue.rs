@@ -0,0 +1,264 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::*;
+use std;
+use std::collections::BTreeSet;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::schema::dead_letters;
+use super::schema::load_queue;
+use super::schema::load_queue::dsl::*;
+
+// After this many failed attempts a height goes to dead_letters.
+pub const MAX_ATTEMPTS: i32 = 8;
+
+/*
+ * The queue of heights waiting to be loaded lives in the load_queue
+ * table, so that it is shared by everything that finds work (scan,
+ * fill_missing_heights, detect_forks) and the loader workers, and
+ * survives a restart. Each height is only ever queued once.
+ *
+ * Workers claim a height with SELECT ... FOR UPDATE SKIP LOCKED inside
+ * the transaction which loads the generation, and delete the row in
+ * that same transaction, so a height leaves the queue exactly when its
+ * generation is committed.
+ *
+ * When a load fails the height stays queued, but isn't handed out
+ * again until next_attempt_at, which backs off exponentially. After
+ * MAX_ATTEMPTS it is moved to the dead_letters table, where it stays
+ * until someone runs with --retry-dead-letters.
+ */
+#[derive(Queryable, QueryableByName)]
+#[table_name = "load_queue"]
+pub struct QueuedHeight {
+    pub height: i64,
+    pub reason: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub enqueued_at: SystemTime,
+    pub next_attempt_at: SystemTime,
+}
+
+impl QueuedHeight {
+    /*
+     * Add a height to the queue. Returns false if it was already
+     * waiting.
+     */
+    pub fn enqueue(conn: &PgConnection, _height: i64, _reason: &str) ->
+        Result<bool, Box<std::error::Error>>
+    {
+        let count = diesel::insert_into(load_queue)
+            .values((height.eq(_height), reason.eq(_reason)))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        Ok(count > 0)
+    }
+
+    /*
+     * Queue every height from 0 to top which has no key block in the
+     * DB. Returns the number of heights added.
+     */
+    pub fn enqueue_missing(conn: &PgConnection, top: i64) ->
+        Result<usize, Box<std::error::Error>>
+    {
+        let count = sql_query(
+            "INSERT INTO load_queue (height, reason) \
+             SELECT s.i, 'missing' FROM generate_series(0, $1) s(i) \
+             WHERE NOT EXISTS (SELECT 1 FROM key_blocks WHERE height = s.i) \
+             ON CONFLICT DO NOTHING")
+            .bind::<BigInt, _>(top)
+            .execute(conn)?;
+        Ok(count)
+    }
+
+    /*
+     * Lock a queued height nobody else is working on. This must be
+     * called inside a transaction, which holds the lock until it ends.
+     * Heights we already hold a key block for (refreshes and forks)
+     * come first, so they aren't stuck behind a backfill. Missing
+     * heights are handed out lowest first, and none above a missing
+     * height which is backing off after a failure, so that loading
+     * them can't leave a gap below.
+     */
+    pub fn claim(conn: &PgConnection) -> Result<Option<QueuedHeight>, Box<std::error::Error>> {
+        let mut rows: Vec<QueuedHeight> = sql_query(
+            "SELECT * FROM load_queue q WHERE q.next_attempt_at <= NOW() \
+             AND (EXISTS (SELECT 1 FROM key_blocks k WHERE k.height = q.height) \
+             OR NOT EXISTS (SELECT 1 FROM load_queue b \
+             WHERE b.height < q.height AND b.next_attempt_at > NOW() \
+             AND NOT EXISTS (SELECT 1 FROM key_blocks k WHERE k.height = b.height))) \
+             ORDER BY EXISTS (SELECT 1 FROM key_blocks k WHERE k.height = q.height) DESC, \
+             q.height \
+             LIMIT 1 FOR UPDATE SKIP LOCKED")
+            .load(conn)?;
+        Ok(rows.pop())
+    }
+
+    /*
+     * Whether a height below this one is queued, missing from the DB,
+     * and not being loaded, i.e. not locked by another worker. If so,
+     * committing this height would leave a gap. Like claim(), this
+     * must be called inside a transaction.
+     */
+    pub fn gap_below(conn: &PgConnection, _height: i64) -> Result<bool, Box<std::error::Error>> {
+        let rows: Vec<QueuedHeight> = sql_query(
+            "SELECT * FROM load_queue q WHERE q.height < $1 \
+             AND NOT EXISTS (SELECT 1 FROM key_blocks k WHERE k.height = q.height) \
+             LIMIT 1 FOR UPDATE SKIP LOCKED")
+            .bind::<BigInt, _>(_height)
+            .load(conn)?;
+        Ok(!rows.is_empty())
+    }
+
+    pub fn remove(conn: &PgConnection, _height: i64) -> Result<(), Box<std::error::Error>> {
+        diesel::delete(load_queue.filter(height.eq(_height))).execute(conn)?;
+        Ok(())
+    }
+
+    /*
+     * Record a failed attempt to load this height, and put off the
+     * next one: 10 seconds after the first failure, doubling each
+     * time up to an hour. Once MAX_ATTEMPTS is reached the height is
+     * moved to dead_letters, along with whatever the node sent us if
+     * that was the problem. Returns true if it was dead-lettered.
+     */
+    pub fn record_failure(conn: &PgConnection, _height: i64, error: &String,
+                          raw_response: Option<String>) ->
+        Result<bool, Box<std::error::Error>>
+    {
+        conn.transaction::<bool, Box<std::error::Error>, _>(|| {
+            let mut rows: Vec<QueuedHeight> = sql_query(
+                "UPDATE load_queue SET attempts = attempts + 1, last_error = $2, \
+                 next_attempt_at = NOW() + \
+                 LEAST(INTERVAL '10 seconds' * POWER(2, attempts), INTERVAL '1 hour') \
+                 WHERE height = $1 RETURNING *")
+                .bind::<BigInt, _>(_height)
+                .bind::<Text, _>(error)
+                .load(conn)?;
+            let item = match rows.pop() {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+            if item.attempts < MAX_ATTEMPTS {
+                return Ok(false);
+            }
+            warn!("Giving up on height {} after {} attempts", _height, item.attempts);
+            let dead = DeadLetter {
+                height: item.height,
+                reason: item.reason,
+                attempts: item.attempts,
+                last_error: item.last_error,
+                raw_response,
+            };
+            diesel::insert_into(dead_letters::table)
+                .values(&dead)
+                .on_conflict(dead_letters::height)
+                .do_update()
+                .set(&dead)
+                .execute(conn)?;
+            QueuedHeight::remove(conn, _height)?;
+            Ok(true)
+        })
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "dead_letters"]
+pub struct DeadLetter {
+    pub height: i64,
+    pub reason: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub raw_response: Option<String>,
+}
+
+impl DeadLetter {
+    /*
+     * Put every dead-lettered height back on the load queue with a
+     * clean slate. Returns the number of heights queued.
+     */
+    pub fn retry_all(conn: &PgConnection) -> Result<usize, Box<std::error::Error>> {
+        conn.transaction::<usize, Box<std::error::Error>, _>(|| {
+            let count = sql_query(
+                "INSERT INTO load_queue (height, reason) \
+                 SELECT height, 'retry' FROM dead_letters \
+                 ON CONFLICT (height) DO UPDATE SET attempts = 0, next_attempt_at = NOW()")
+                .execute(conn)?;
+            diesel::delete(dead_letters::table).execute(conn)?;
+            Ok(count)
+        })
+    }
+}
+
+/*
+ * Workers fetch in parallel, but each waits in wait_turn() until it
+ * holds the lowest height in flight in this process before
+ * committing, so the DB never gets a height while a lower one which
+ * is being loaded is still missing. A height is in flight for as long
+ * as the InFlight returned by start() lives, so it is let go even if
+ * the worker panics.
+ */
+pub struct CommitOrder {
+    in_flight: Mutex<BTreeSet<i64>>,
+    changed: Condvar,
+}
+
+impl CommitOrder {
+    pub fn new() -> CommitOrder {
+        CommitOrder {
+            in_flight: Mutex::new(BTreeSet::new()),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn start(&self, _height: i64) -> InFlight {
+        self.lock().insert(_height);
+        InFlight { order: self, height: _height }
+    }
+
+    /*
+     * A panicking worker can't leave the set half changed, so carry on
+     * if the lock was poisoned rather than stopping every worker.
+     */
+    fn lock(&self) -> MutexGuard<BTreeSet<i64>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /*
+     * Block until this height is the lowest one in flight.
+     */
+    fn wait_turn(&self, _height: i64) {
+        let mut in_flight = self.lock();
+        while in_flight.iter().next() != Some(&_height) {
+            in_flight = self.changed.wait(in_flight).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /*
+     * The worker is finished with this height, whether it succeeded
+     * or not.
+     */
+    fn done(&self, _height: i64) {
+        self.lock().remove(&_height);
+        self.changed.notify_all();
+    }
+}
+
+pub struct InFlight<'a> {
+    order: &'a CommitOrder,
+    pub height: i64,
+}
+
+impl<'a> InFlight<'a> {
+    pub fn wait_turn(&self) {
+        self.order.wait_turn(self.height);
+    }
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.order.done(self.height);
+    }
+}
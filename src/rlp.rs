@@ -0,0 +1,210 @@
+use std;
+
+/*
+ * Recursive Length Prefix encoding, which the node uses to serialize
+ * everything: an item is either a byte string or a list of items.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+fn length_prefix(len: usize, offset: u8) -> Vec<u8> {
+    if len <= 55 {
+        return vec!(offset + len as u8);
+    }
+    let len_bytes = uint_to_bytes(len as u64);
+    let mut prefix = vec!(offset + 55 + len_bytes.len() as u8);
+    prefix.extend(len_bytes);
+    prefix
+}
+
+/*
+ * The length of a byte string or list encoded with a payload of len
+ * bytes, except for a single byte below 0x80, which is itself.
+ */
+pub fn encoded_len(len: usize) -> usize {
+    length_prefix(len, 0).len() + len
+}
+
+/*
+ * Big-endian, without leading zeroes. Zero is no bytes at all.
+ */
+pub fn uint_to_bytes(value: u64) -> Vec<u8> {
+    let bytes: Vec<u8> = (0..8).rev().map(|i| (value >> (i * 8)) as u8).collect();
+    bytes.into_iter().skip_while(|x| *x == 0).collect()
+}
+
+pub fn bytes_to_uint(bytes: &[u8]) -> Result<u64, Box<std::error::Error>> {
+    if bytes.len() > 8 {
+        return Err(From::from("Integer too big"));
+    }
+    Ok(bytes.iter().fold(0u64, |acc, x| (acc << 8) | *x as u64))
+}
+
+pub fn encode(item: &Rlp) -> Vec<u8> {
+    match *item {
+        Rlp::Bytes(ref bytes) => {
+            if bytes.len() == 1 && bytes[0] < 0x80 {
+                return bytes.clone();
+            }
+            let mut encoded = length_prefix(bytes.len(), 0x80);
+            encoded.extend(bytes);
+            encoded
+        },
+        Rlp::List(ref items) => {
+            let mut payload = vec!();
+            for i in items {
+                payload.extend(encode(i));
+            }
+            let mut encoded = length_prefix(payload.len(), 0xc0);
+            encoded.extend(payload);
+            encoded
+        },
+    }
+}
+
+/*
+ * How many levels of lists may be nested inside each other. Nothing
+ * the node serializes comes close, and decoding recurses once per level.
+ */
+const MAX_DEPTH: usize = 32;
+
+/*
+ * The whole of data must be one item.
+ */
+pub fn decode(data: &[u8]) -> Result<Rlp, Box<std::error::Error>> {
+    let (item, rest) = decode_item(data, 0)?;
+    if !rest.is_empty() {
+        return Err(From::from(format!("{} bytes left over after RLP item", rest.len())));
+    }
+    Ok(item)
+}
+
+fn split(data: &[u8], start: usize, len: u64) -> Result<(&[u8], &[u8]), Box<std::error::Error>> {
+    let end = match start.checked_add(len as usize) {
+        Some(x) if len <= data.len() as u64 && x <= data.len() => x,
+        _ => return Err(From::from("RLP item runs past the end of the data")),
+    };
+    Ok((&data[start..end], &data[end..]))
+}
+
+fn decode_item(data: &[u8], depth: usize) -> Result<(Rlp, &[u8]), Box<std::error::Error>> {
+    if data.is_empty() {
+        return Err(From::from("Empty RLP item"));
+    }
+    let first = data[0];
+    let (is_list, payload, rest) = match first {
+        0x00...0x7f => (false, &data[0..1], &data[1..]),
+        0x80...0xb7 => {
+            let (p, r) = split(data, 1, (first - 0x80) as u64)?;
+            (false, p, r)
+        },
+        0xb8...0xbf => {
+            let (len, _) = split(data, 1, (first - 0xb7) as u64)?;
+            let (p, r) = split(data, 1 + len.len(), bytes_to_uint(len)?)?;
+            (false, p, r)
+        },
+        0xc0...0xf7 => {
+            let (p, r) = split(data, 1, (first - 0xc0) as u64)?;
+            (true, p, r)
+        },
+        _ => {
+            let (len, _) = split(data, 1, (first - 0xf7) as u64)?;
+            let (p, r) = split(data, 1 + len.len(), bytes_to_uint(len)?)?;
+            (true, p, r)
+        },
+    };
+    if !is_list {
+        return Ok((Rlp::Bytes(payload.to_vec()), rest));
+    }
+    if depth >= MAX_DEPTH {
+        return Err(From::from(format!("RLP lists nested more than {} deep", MAX_DEPTH)));
+    }
+    let mut items = vec!();
+    let mut remaining = payload;
+    while !remaining.is_empty() {
+        let (item, r) = decode_item(remaining, depth + 1)?;
+        items.push(item);
+        remaining = r;
+    }
+    Ok((Rlp::List(items), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // levels lists, one inside the other
+    fn nested(levels: usize) -> Rlp {
+        let mut item = Rlp::List(vec!());
+        for _ in 1..levels {
+            item = Rlp::List(vec!(item));
+        }
+        item
+    }
+
+    #[test]
+    fn round_trips() {
+        let items = vec!(
+            Rlp::Bytes(vec!()),
+            Rlp::Bytes(vec!(0x7f)),
+            Rlp::Bytes(vec!(0x80)),
+            Rlp::Bytes(vec!(1; 55)),
+            Rlp::Bytes(vec!(2; 56)),
+            Rlp::Bytes(vec!(3; 1024)),
+            Rlp::List(vec!()),
+            Rlp::List(vec!(Rlp::Bytes(vec!(4; 60)), Rlp::List(vec!(Rlp::Bytes(vec!(5)))))),
+            nested(MAX_DEPTH),
+        );
+        for item in items {
+            assert_eq!(decode(&encode(&item)).unwrap(), item);
+        }
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(encode(&Rlp::Bytes(b"dog".to_vec())), vec!(0x83, b'd', b'o', b'g'));
+        assert_eq!(encode(&Rlp::List(vec!())), vec!(0xc0));
+        assert_eq!(encode(&Rlp::Bytes(vec!(0; 56)))[0..2], [0xb8, 56]);
+        assert_eq!(uint_to_bytes(0), Vec::<u8>::new());
+        assert_eq!(uint_to_bytes(1024), vec!(4, 0));
+        assert_eq!(bytes_to_uint(&[4, 0]).unwrap(), 1024);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let bad: Vec<Vec<u8>> = vec!(
+            vec!(),
+            // runs past the end
+            vec!(0x83, b'd', b'o'),
+            vec!(0xc2, 0x01),
+            // lengths which overflow when added to the offset
+            vec!(0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff),
+            vec!(0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00),
+            // a length with nothing after it
+            vec!(0xb8, 0x01),
+            // left over bytes
+            vec!(0x01, 0x02),
+            vec!(0xc1, 0xc1),
+        );
+        for data in bad {
+            assert!(decode(&data).is_err(), "{:?} decoded", data);
+        }
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        assert!(decode(&encode(&nested(MAX_DEPTH))).is_ok());
+        assert!(decode(&encode(&nested(MAX_DEPTH + 1))).is_err());
+        // built up directly, as encode() recurses too
+        let mut data = vec!(0xc0);
+        for _ in 0..5000 {
+            let mut wrapped = length_prefix(data.len(), 0xc0);
+            wrapped.extend(data);
+            data = wrapped;
+        }
+        assert!(decode(&data).is_err());
+    }
+}
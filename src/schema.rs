@@ -1,3 +1,22 @@
+table! {
+    account_nonces (account) {
+        account -> Varchar,
+        nonce -> Int8,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    dead_letters (height) {
+        height -> Int8,
+        reason -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        raw_response -> Nullable<Text>,
+        failed_at -> Timestamp,
+    }
+}
+
 table! {
     key_blocks (id) {
         id -> Int4,
@@ -29,6 +48,43 @@ table! {
         state_hash -> Varchar,
         txs_hash -> Varchar,
         version -> Int4,
+        #[sql_name="time_"]
+        time -> Int8,
+    }
+}
+
+table! {
+    submissions (id) {
+        id -> Int4,
+        hash -> Varchar,
+        remote_addr -> Nullable<Varchar>,
+        forwarded_for -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+        submitted_at -> Timestamp,
+        status -> Varchar,
+        block_height -> Nullable<Int4>,
+        micro_block_hash -> Nullable<Varchar>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    transaction_events (id) {
+        id -> Int4,
+        transaction_hash -> Varchar,
+        event -> Varchar,
+        block_height -> Nullable<Int4>,
+        micro_block_hash -> Nullable<Varchar>,
+        happened_at -> Timestamp,
+    }
+}
+
+table! {
+    transaction_participants (id) {
+        id -> Int4,
+        transaction_id -> Int4,
+        account_id -> Varchar,
+        role -> Varchar,
     }
 }
 
@@ -45,10 +101,119 @@ table! {
         fee -> Int8,
         size -> Int4,
         valid -> Bool,
+        first_seen -> Nullable<Timestamp>,
+        mined_at -> Nullable<Timestamp>,
+        micro_block_index -> Int4,
+        transaction_index -> Int4,
+    }
+}
+
+table! {
+    load_queue (height) {
+        height -> Int8,
+        reason -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        enqueued_at -> Timestamp,
+        next_attempt_at -> Timestamp,
+    }
+}
+
+table! {
+    orphaned_key_blocks (id) {
+        id -> Int4,
+        hash -> Varchar,
+        height -> Int8,
+        miner -> Nullable<Varchar>,
+        beneficiary -> Nullable<Varchar>,
+        nonce -> Nullable<Numeric>,
+        pow -> Nullable<Text>,
+        prev_hash -> Nullable<Varchar>,
+        prev_key_hash -> Nullable<Varchar>,
+        state_hash -> Nullable<Varchar>,
+        target -> Nullable<Int8>,
+        #[sql_name="time_"]
+        time -> Nullable<Int8>,
+        version -> Nullable<Int4>,
+        orphaned_at -> Timestamp,
+    }
+}
+
+table! {
+    orphaned_micro_blocks (id) {
+        id -> Int4,
+        key_block_hash -> Varchar,
+        height -> Int8,
+        hash -> Varchar,
+        pof_hash -> Varchar,
+        prev_hash -> Varchar,
+        prev_key_hash -> Varchar,
+        signature -> Varchar,
+        state_hash -> Varchar,
+        txs_hash -> Varchar,
+        version -> Int4,
+        orphaned_at -> Timestamp,
+    }
+}
+
+table! {
+    orphaned_transactions (id) {
+        id -> Int4,
+        micro_block_hash -> Varchar,
+        key_block_hash -> Varchar,
+        block_height -> Int4,
+        hash -> Varchar,
+        signatures -> Text,
+        tx_type -> Varchar,
+        tx -> Jsonb,
+        fee -> Int8,
+        size -> Int4,
+        orphaned_at -> Timestamp,
+    }
+}
+
+table! {
+    proofs_of_fraud (id) {
+        id -> Int4,
+        micro_block_hash -> Varchar,
+        pof_hash -> Varchar,
+        height -> Int8,
+        reporter -> Varchar,
+        offender -> Nullable<Varchar>,
+        detected_at -> Timestamp,
+    }
+}
+
+table! {
+    reorgs (id) {
+        id -> Int4,
+        detected_at -> Timestamp,
+        from_height -> Int8,
+        to_height -> Int8,
+        old_hashes -> Array<Varchar>,
+        new_hashes -> Array<Varchar>,
+        transactions_affected -> Int4,
+        kind -> Varchar,
     }
 }
 
 joinable!(micro_blocks -> key_blocks (key_block_id));
+joinable!(transaction_participants -> transactions (transaction_id));
 joinable!(transactions -> micro_blocks (micro_block_id));
 
-allow_tables_to_appear_in_same_query!(key_blocks, micro_blocks, transactions,);
+allow_tables_to_appear_in_same_query!(
+    account_nonces,
+    dead_letters,
+    key_blocks,
+    load_queue,
+    micro_blocks,
+    orphaned_key_blocks,
+    orphaned_micro_blocks,
+    orphaned_transactions,
+    proofs_of_fraud,
+    reorgs,
+    submissions,
+    transaction_events,
+    transaction_participants,
+    transactions,
+);
@@ -0,0 +1,166 @@
+use diesel::pg::PgConnection;
+use regex::Regex;
+use serde_json;
+use serde_json::Value;
+use std;
+
+use encoding;
+use epoch::Epoch;
+use models::{has_participated, JsonKeyBlock, JsonMicroBlockHeader, JsonTransaction, KeyBlock,
+             Transaction};
+
+/*
+ * What each prefix we can search for is.
+ */
+const PREFIX_TYPES: [(&str, &str); 8] = [
+    ("kh", "key_block"),
+    ("mh", "micro_block"),
+    ("th", "transaction"),
+    ("ak", "account"),
+    ("ct", "contract"),
+    ("ok", "oracle"),
+    ("nm", "name"),
+    ("ch", "channel"),
+];
+
+lazy_static! {
+    /*
+     * What a name can be. Names go into the path of a request to the
+     * node, so nothing else may be looked up as one.
+     */
+    static ref NAME: Regex = Regex::new(r"^[a-z0-9-]+(\.[a-z0-9-]+)*\.(test|aet)$").unwrap();
+}
+
+/*
+ * What a search found: its type, e.g. "key_block" or "account", where
+ * it came from, "middleware" or "node", and the record itself, as the
+ * node gives it.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct SearchResult {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub source: String,
+    pub record: Value,
+}
+
+/*
+ * Work out what the query is: a height, a hash or id with a valid
+ * checksum, or a name such as "example.test", in lower case and ending
+ * in .test or .aet.
+ */
+pub fn classify(query: &str) -> Result<&'static str, Box<std::error::Error>> {
+    if !query.is_empty() && query.chars().all(|x| x.is_ascii_digit()) {
+        return Ok("key_block");
+    }
+    if query.get(2..3) == Some("_") {
+        let (prefix, _) = encoding::decode(query)?;
+        return match PREFIX_TYPES.iter().find(|x| x.0 == prefix) {
+            Some(x) => Ok(x.1),
+            None => Err(From::from(format!("Can't search for {}_ values", prefix))),
+        };
+    }
+    if NAME.is_match(query) {
+        return Ok("name");
+    }
+    Err(From::from(format!("{} isn't a height, hash, id or name", query)))
+}
+
+/*
+ * Look for what the query refers to, in the DB first and then in the
+ * node. We don't keep the state of accounts, contracts, oracles, names
+ * or channels, so those come from the node, unless it doesn't know one
+ * which we have seen in transactions, when the record is just its id.
+ * An error means the query is malformed.
+ */
+pub fn search(conn: &PgConnection, epoch: &Epoch, query: &str) ->
+    Result<Option<SearchResult>, Box<std::error::Error>>
+{
+    let kind = classify(query)?;
+    let found = |source: &str, record: Value| Some(SearchResult {
+        kind: String::from(kind),
+        source: String::from(source),
+        record,
+    });
+    if let Some(record) = from_db(conn, kind, query)? {
+        return Ok(found("middleware", record));
+    }
+    if let Some(record) = from_node(epoch, kind, query) {
+        return Ok(found("node", record));
+    }
+    if query.get(2..3) == Some("_") && has_participated(conn, &String::from(query))? {
+        let mut record = serde_json::Map::new();
+        record.insert(String::from("id"), Value::String(String::from(query)));
+        return Ok(found("middleware", Value::Object(record)));
+    }
+    Ok(None)
+}
+
+fn from_db(conn: &PgConnection, kind: &str, query: &str) ->
+    Result<Option<Value>, Box<std::error::Error>>
+{
+    let query = String::from(query);
+    Ok(match kind {
+        "key_block" => {
+            let key_block = match query.parse() {
+                Ok(height) => KeyBlock::load_at_height(conn, height),
+                Err(_) => KeyBlock::load_at_hash(conn, &query),
+            };
+            match key_block {
+                Some(x) => Some(serde_json::to_value(&JsonKeyBlock::from_key_block(&x))?),
+                None => None,
+            }
+        },
+        "micro_block" => match JsonMicroBlockHeader::load_at_hash(conn, &query)? {
+            Some(x) => Some(serde_json::to_value(&x)?),
+            None => None,
+        },
+        "transaction" => match Transaction::load_at_hash(conn, &query) {
+            Some(x) => Some(serde_json::to_value(&JsonTransaction::from_transaction(&x))?),
+            None => None,
+        },
+        _ => None,
+    })
+}
+
+fn from_node(epoch: &Epoch, kind: &str, query: &str) -> Option<Value> {
+    let path = match kind {
+        "key_block" if query.starts_with("kh_") => format!("key-blocks/hash/{}", query),
+        "key_block" => format!("key-blocks/height/{}", query),
+        "micro_block" => format!("micro-blocks/hash/{}/header", query),
+        "transaction" => format!("transactions/{}", query),
+        "account" => format!("accounts/{}", query),
+        "contract" => format!("contracts/{}", query),
+        "oracle" => format!("oracles/{}", query),
+        "channel" => format!("channels/{}", query),
+        // the node only looks names up by name, not by their nm_ id
+        "name" if NAME.is_match(query) => format!("names/{}", query),
+        _ => return None,
+    };
+    match epoch.get(&path) {
+        // the node says why it couldn't find something in "reason"
+        Ok(ref x) if x.get("reason").is_some() => None,
+        Ok(x) => Some(x),
+        Err(e) => {
+            error!("Error searching the node for {}: {:?}", query, e);
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_names() {
+        for x in ["example.test", "my-name.test", "a.b.c.aet", "1.test"].iter() {
+            assert_eq!(classify(x).unwrap(), "name", "{}", x);
+        }
+        for x in ["a.test/../../debug?x", "a.test?x=1", "a.test#x", "../a.test", "a..test",
+                  ".test", "Example.test", "example.com", "a.test/", "a b.test",
+                  "a%2e.test", "a.test\n"].iter() {
+            assert!(classify(x).is_err(), "{} classified", x);
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use crypto::blake2b::Blake2b;
+use serde_json;
+use serde_json::Value;
+use std;
+
+use encoding;
+use models::JsonTransaction;
+use rlp;
+use rlp::Rlp;
+
+/*
+ * How the node serializes transactions, so that we can work out their
+ * real size and hash from the JSON it gives us. A transaction is an
+ * RLP list of its type's tag, the version of the format, and its
+ * fields in the order given here, named as they are in the JSON.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    // "ak_...", "nm_..." etc., as a 33 byte id
+    Id,
+    // a non-negative integer of any size
+    Int,
+    // binary, given in the JSON with this prefix
+    Binary(&'static str),
+    // binary, given in the JSON as text
+    Text,
+    // {"type": "delta" or "block", "value": n}, as two fields
+    Ttl,
+    // a list of ids
+    Ids,
+    // a list of {"key": text, "id": id}
+    Pointers,
+}
+
+use self::Field::*;
+
+pub struct TxFormat {
+    pub tx_type: &'static str,
+    pub tag: u64,
+    pub version: u64,
+    pub fields: &'static [(&'static str, Field)],
+}
+
+pub const SIGNED_TX_TAG: u64 = 11;
+pub const SIGNED_TX_VERSION: u64 = 1;
+
+pub const TX_FORMATS: &[TxFormat] = &[
+    TxFormat { tx_type: "SpendTx", tag: 12, version: 1, fields: &[
+        ("sender_id", Id), ("recipient_id", Id), ("amount", Int), ("fee", Int),
+        ("ttl", Int), ("nonce", Int), ("payload", Binary("ba"))] },
+    TxFormat { tx_type: "OracleRegisterTx", tag: 22, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("query_format", Text), ("response_format", Text),
+        ("query_fee", Int), ("oracle_ttl", Ttl), ("fee", Int), ("ttl", Int),
+        ("vm_version", Int)] },
+    TxFormat { tx_type: "OracleQueryTx", tag: 23, version: 1, fields: &[
+        ("sender_id", Id), ("nonce", Int), ("oracle_id", Id), ("query", Text),
+        ("query_fee", Int), ("query_ttl", Ttl), ("response_ttl", Ttl), ("fee", Int),
+        ("ttl", Int)] },
+    TxFormat { tx_type: "OracleResponseTx", tag: 24, version: 1, fields: &[
+        ("oracle_id", Id), ("nonce", Int), ("query_id", Binary("oq")), ("response", Text),
+        ("response_ttl", Ttl), ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "OracleExtendTx", tag: 25, version: 1, fields: &[
+        ("oracle_id", Id), ("nonce", Int), ("oracle_ttl", Ttl), ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "NameClaimTx", tag: 32, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("name", Text), ("name_salt", Int),
+        ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "NamePreclaimTx", tag: 33, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("commitment_id", Id), ("fee", Int),
+        ("ttl", Int)] },
+    TxFormat { tx_type: "NameUpdateTx", tag: 34, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("name_id", Id), ("name_ttl", Int),
+        ("pointers", Pointers), ("client_ttl", Int), ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "NameRevokeTx", tag: 35, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("name_id", Id), ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "NameTransferTx", tag: 36, version: 1, fields: &[
+        ("account_id", Id), ("nonce", Int), ("name_id", Id), ("recipient_id", Id),
+        ("fee", Int), ("ttl", Int)] },
+    TxFormat { tx_type: "ContractCreateTx", tag: 42, version: 1, fields: &[
+        ("owner_id", Id), ("nonce", Int), ("code", Binary("cb")), ("vm_version", Int),
+        ("fee", Int), ("ttl", Int), ("deposit", Int), ("amount", Int), ("gas", Int),
+        ("gas_price", Int), ("call_data", Binary("cb"))] },
+    TxFormat { tx_type: "ContractCallTx", tag: 43, version: 1, fields: &[
+        ("caller_id", Id), ("nonce", Int), ("contract_id", Id), ("vm_version", Int),
+        ("fee", Int), ("ttl", Int), ("amount", Int), ("gas", Int), ("gas_price", Int),
+        ("call_data", Binary("cb"))] },
+    TxFormat { tx_type: "ChannelCreateTx", tag: 50, version: 1, fields: &[
+        ("initiator_id", Id), ("initiator_amount", Int), ("responder_id", Id),
+        ("responder_amount", Int), ("channel_reserve", Int), ("lock_period", Int),
+        ("ttl", Int), ("fee", Int), ("delegate_ids", Ids), ("state_hash", Binary("st")),
+        ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelDepositTx", tag: 51, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("amount", Int), ("ttl", Int), ("fee", Int),
+        ("state_hash", Binary("st")), ("round", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelWithdrawTx", tag: 52, version: 1, fields: &[
+        ("channel_id", Id), ("to_id", Id), ("amount", Int), ("ttl", Int), ("fee", Int),
+        ("state_hash", Binary("st")), ("round", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelCloseMutualTx", tag: 53, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("initiator_amount_final", Int),
+        ("responder_amount_final", Int), ("ttl", Int), ("fee", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelCloseSoloTx", tag: 54, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("payload", Binary("tx")), ("poi", Binary("pi")),
+        ("ttl", Int), ("fee", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelSlashTx", tag: 55, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("payload", Binary("tx")), ("poi", Binary("pi")),
+        ("ttl", Int), ("fee", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelSettleTx", tag: 56, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("initiator_amount_final", Int),
+        ("responder_amount_final", Int), ("ttl", Int), ("fee", Int), ("nonce", Int)] },
+    TxFormat { tx_type: "ChannelSnapshotSoloTx", tag: 59, version: 1, fields: &[
+        ("channel_id", Id), ("from_id", Id), ("payload", Binary("tx")), ("ttl", Int),
+        ("fee", Int), ("nonce", Int)] },
+];
+
+pub fn format_for_type(tx_type: &str) -> Option<&'static TxFormat> {
+    TX_FORMATS.iter().find(|x| x.tx_type == tx_type)
+}
+
+const TTL_TYPES: [&str; 2] = ["delta", "block"];
+
+/*
+ * Decimal digits to big-endian bytes, without leading zeroes.
+ */
+pub fn decimal_to_bytes(decimal: &str) -> Result<Vec<u8>, Box<std::error::Error>> {
+    let mut digits: Vec<u32> = match decimal.chars().map(|c| c.to_digit(10)).collect() {
+        Some(x) => x,
+        None => return Err(From::from(format!("{} isn't a non-negative integer", decimal))),
+    };
+    if digits.is_empty() {
+        return Err(From::from("Empty integer"));
+    }
+    let mut bytes = vec!();
+    while !digits.is_empty() {
+        let mut remainder = 0;
+        let mut quotient = vec!();
+        for d in digits {
+            let current = remainder * 10 + d;
+            if !quotient.is_empty() || current >= 256 {
+                quotient.push(current / 256);
+            }
+            remainder = current % 256;
+        }
+        bytes.push(remainder as u8);
+        digits = quotient;
+    }
+    bytes.reverse();
+    Ok(bytes.into_iter().skip_while(|x| *x == 0).collect())
+}
+
+/*
+ * Big-endian bytes to decimal digits.
+ */
+pub fn bytes_to_decimal(bytes: &[u8]) -> String {
+    let mut digits: Vec<u32> = vec!(0);
+    for b in bytes {
+        let mut carry = *b as u32;
+        for d in digits.iter_mut().rev() {
+            let current = *d * 256 + carry;
+            *d = current % 10;
+            carry = current / 10;
+        }
+        while carry > 0 {
+            digits.insert(0, carry % 10);
+            carry /= 10;
+        }
+    }
+    digits.iter().map(|d| std::char::from_digit(*d, 10).unwrap()).collect()
+}
+
+fn int_bytes(name: &str, value: &Value) -> Result<Vec<u8>, Box<std::error::Error>> {
+    match *value {
+        // a field the node left out is 0
+        Value::Null => Ok(vec!()),
+        Value::Number(ref n) => decimal_to_bytes(&n.to_string()),
+        Value::String(ref s) => decimal_to_bytes(s),
+        _ => Err(From::from(format!("{} should be an integer", name))),
+    }
+}
+
+fn str_value<'a>(name: &str, value: &'a Value) -> Result<&'a str, Box<std::error::Error>> {
+    match value.as_str() {
+        Some(x) => Ok(x),
+        None => Err(From::from(format!("{} should be a string", name))),
+    }
+}
+
+/*
+ * Binary values are usually given with a prefix, but some nodes give
+ * payloads and the like as plain text.
+ */
+fn binary_bytes(value: &Value) -> Vec<u8> {
+    match value.as_str() {
+        Some(x) => match encoding::decode(x) {
+            Ok((_, bytes)) => bytes,
+            Err(_) => x.as_bytes().to_vec(),
+        },
+        None => vec!(),
+    }
+}
+
+fn serialize_field(name: &str, field: Field, value: &Value) -> Result<Vec<Rlp>, Box<std::error::Error>> {
+    let items = match field {
+        Id => vec!(Rlp::Bytes(encoding::encode_id(str_value(name, value)?)?)),
+        Int => vec!(Rlp::Bytes(int_bytes(name, value)?)),
+        Binary(_) | Text => vec!(Rlp::Bytes(binary_bytes(value))),
+        Ttl => {
+            let ttl_type = str_value(name, &value["type"])?;
+            let ttl_type = match TTL_TYPES.iter().position(|x| *x == ttl_type) {
+                Some(x) => x as u64,
+                None => return Err(From::from(format!("Unknown {} type {}", name, ttl_type))),
+            };
+            vec!(Rlp::Bytes(rlp::uint_to_bytes(ttl_type)),
+                 Rlp::Bytes(int_bytes(name, &value["value"])?))
+        },
+        Ids => {
+            let mut ids = vec!();
+            for id in value.as_array().unwrap_or(&vec!()) {
+                ids.push(Rlp::Bytes(encoding::encode_id(str_value(name, id)?)?));
+            }
+            vec!(Rlp::List(ids))
+        },
+        Pointers => {
+            let mut pointers = vec!();
+            for p in value.as_array().unwrap_or(&vec!()) {
+                pointers.push(Rlp::List(vec!(
+                    Rlp::Bytes(binary_bytes(&p["key"])),
+                    Rlp::Bytes(encoding::encode_id(str_value(name, &p["id"])?)?))));
+            }
+            vec!(Rlp::List(pointers))
+        },
+    };
+    Ok(items)
+}
+
+fn bytes_item<'a>(name: &str, item: &'a Rlp) -> Result<&'a Vec<u8>, Box<std::error::Error>> {
+    match *item {
+        Rlp::Bytes(ref x) => Ok(x),
+        Rlp::List(_) => Err(From::from(format!("{} should be bytes, not a list", name))),
+    }
+}
+
+fn list_item<'a>(name: &str, item: &'a Rlp) -> Result<&'a Vec<Rlp>, Box<std::error::Error>> {
+    match *item {
+        Rlp::List(ref x) => Ok(x),
+        Rlp::Bytes(_) => Err(From::from(format!("{} should be a list, not bytes", name))),
+    }
+}
+
+fn int_value(name: &str, item: &Rlp) -> Result<Value, Box<std::error::Error>> {
+    let bytes = bytes_item(name, item)?;
+    // arbitrary_precision lets this hold integers of any size
+    Ok(serde_json::from_str(&bytes_to_decimal(bytes))?)
+}
+
+fn text_value(name: &str, item: &Rlp) -> Result<Value, Box<std::error::Error>> {
+    Ok(Value::String(String::from_utf8_lossy(bytes_item(name, item)?).into_owned()))
+}
+
+/*
+ * Turn this field's items back into JSON. Returns the value and how
+ * many items it took.
+ */
+fn deserialize_field(name: &str, field: Field, items: &[Rlp]) ->
+    Result<(Value, usize), Box<std::error::Error>>
+{
+    let needed = if field == Ttl { 2 } else { 1 };
+    if items.len() < needed {
+        return Err(From::from(format!("Transaction ends before {}", name)));
+    }
+    let item = &items[0];
+    let value = match field {
+        Id => Value::String(encoding::decode_id(bytes_item(name, item)?)?),
+        Int => int_value(name, item)?,
+        Binary(prefix) => Value::String(encoding::encode(prefix, bytes_item(name, item)?)),
+        Text => text_value(name, item)?,
+        Ttl => {
+            let ttl_type = rlp::bytes_to_uint(bytes_item(name, item)?)? as usize;
+            if ttl_type >= TTL_TYPES.len() {
+                return Err(From::from(format!("Unknown {} type {}", name, ttl_type)));
+            }
+            let mut ttl = serde_json::Map::new();
+            ttl.insert(String::from("type"), Value::String(String::from(TTL_TYPES[ttl_type])));
+            ttl.insert(String::from("value"), int_value(name, &items[1])?);
+            Value::Object(ttl)
+        },
+        Ids => {
+            let mut ids = vec!();
+            for id in list_item(name, item)? {
+                ids.push(Value::String(encoding::decode_id(bytes_item(name, id)?)?));
+            }
+            Value::Array(ids)
+        },
+        Pointers => {
+            let mut pointers = vec!();
+            for p in list_item(name, item)? {
+                let pair = list_item(name, p)?;
+                if pair.len() != 2 {
+                    return Err(From::from(format!("{} should be pairs", name)));
+                }
+                let mut pointer = serde_json::Map::new();
+                pointer.insert(String::from("key"), text_value(name, &pair[0])?);
+                pointer.insert(String::from("id"),
+                               Value::String(encoding::decode_id(bytes_item(name, &pair[1])?)?));
+                pointers.push(Value::Object(pointer));
+            }
+            Value::Array(pointers)
+        },
+    };
+    Ok((value, needed))
+}
+
+/*
+ * The transaction itself, as in the "tx" of the JSON.
+ */
+pub fn serialize_tx(tx: &Value) -> Result<Rlp, Box<std::error::Error>> {
+    let tx_type = str_value("type", &tx["type"])?;
+    let format = match format_for_type(tx_type) {
+        Some(x) => x,
+        None => return Err(From::from(format!("Unknown transaction type {}", tx_type))),
+    };
+    let mut items = vec!(Rlp::Bytes(rlp::uint_to_bytes(format.tag)),
+                         Rlp::Bytes(rlp::uint_to_bytes(format.version)));
+    for &(name, field) in format.fields {
+        items.extend(serialize_field(name, field, &tx[name])?);
+    }
+    Ok(Rlp::List(items))
+}
+
+/*
+ * The JSON the node would give for a serialized transaction.
+ */
+pub fn deserialize_tx(serialized: &[u8]) -> Result<Value, Box<std::error::Error>> {
+    let decoded = rlp::decode(serialized)?;
+    let items = list_item("transaction", &decoded)?;
+    if items.len() < 2 {
+        return Err(From::from("Transaction has no tag and version"));
+    }
+    let tag = rlp::bytes_to_uint(bytes_item("tag", &items[0])?)?;
+    let version = rlp::bytes_to_uint(bytes_item("version", &items[1])?)?;
+    let format = match TX_FORMATS.iter().find(|x| x.tag == tag) {
+        Some(x) => x,
+        None => return Err(From::from(format!("Unknown transaction tag {}", tag))),
+    };
+    if version != format.version {
+        return Err(From::from(format!("Can't read version {} of {}", version, format.tx_type)));
+    }
+    let mut tx = serde_json::Map::new();
+    let mut position = 2;
+    for &(name, field) in format.fields {
+        let (value, used) = deserialize_field(name, field, &items[position..])?;
+        tx.insert(String::from(name), value);
+        position += used;
+    }
+    if position != items.len() {
+        return Err(From::from(format!("{} has {} fields too many",
+                                      format.tx_type, items.len() - position)));
+    }
+    tx.insert(String::from("type"), Value::String(String::from(format.tx_type)));
+    tx.insert(String::from("version"), Value::from(version));
+    Ok(Value::Object(tx))
+}
+
+/*
+ * A "tx_..." signed transaction, as the node would give it back to us
+ * before it is mined.
+ */
+pub fn decode_signed_tx(encoded: &str) -> Result<JsonTransaction, Box<std::error::Error>> {
+    let serialized = encoding::decode_as("tx", encoded)?;
+    let decoded = rlp::decode(&serialized)?;
+    let items = list_item("signed transaction", &decoded)?;
+    if items.len() != 4 {
+        return Err(From::from("A signed transaction should have 4 fields"));
+    }
+    let tag = rlp::bytes_to_uint(bytes_item("tag", &items[0])?)?;
+    let version = rlp::bytes_to_uint(bytes_item("version", &items[1])?)?;
+    if tag != SIGNED_TX_TAG || version != SIGNED_TX_VERSION {
+        return Err(From::from(format!("Not a signed transaction (tag {}, version {})",
+                                      tag, version)));
+    }
+    let mut signatures = vec!();
+    for s in list_item("signatures", &items[2])? {
+        signatures.push(encoding::encode("sg", bytes_item("signature", s)?));
+    }
+    Ok(JsonTransaction {
+        block_height: -1,
+        block_hash: String::from("none"),
+        hash: tx_hash(&serialized).parse()?,
+        signatures,
+        tx: deserialize_tx(bytes_item("transaction", &items[3])?)?,
+    })
+}
+
+pub fn serialize_signed_tx(signatures: &Vec<String>, tx: &Value) ->
+    Result<Vec<u8>, Box<std::error::Error>>
+{
+    let mut sigs = vec!();
+    for s in signatures {
+        sigs.push(Rlp::Bytes(encoding::decode_as("sg", s)?));
+    }
+    Ok(rlp::encode(&Rlp::List(vec!(
+        Rlp::Bytes(rlp::uint_to_bytes(SIGNED_TX_TAG)),
+        Rlp::Bytes(rlp::uint_to_bytes(SIGNED_TX_VERSION)),
+        Rlp::List(sigs),
+        Rlp::Bytes(rlp::encode(&serialize_tx(tx)?))))))
+}
+
+/*
+ * The hash of a serialized signed transaction, "th_...".
+ */
+pub fn tx_hash(signed_tx: &[u8]) -> String {
+    let mut hash = [0u8; 32];
+    Blake2b::blake2b(&mut hash, signed_tx, &[]);
+    encoding::encode("th", &hash)
+}
+
+/*
+ * The size in bytes of the serialized signed transaction. If it
+ * doesn't hash to the transaction's hash then we have serialized it
+ * differently to the node, and the size would be wrong too.
+ */
+pub fn signed_tx_size(jt: &JsonTransaction) -> Result<usize, Box<std::error::Error>> {
+    let serialized = serialize_signed_tx(&jt.signatures, &jt.tx)?;
+    let hash = tx_hash(&serialized);
+    if hash != *jt.hash {
+        return Err(From::from(format!("{} serializes to a transaction with hash {}",
+                                      jt.hash, hash)));
+    }
+    Ok(serialized.len())
+}
+
+/*
+ * The size of the transaction inside a serialized signed transaction
+ * of signed_size bytes with signature_count signatures, which is the
+ * size the node charges gas for. None if no transaction fits exactly.
+ */
+pub fn unsigned_tx_size(signed_size: usize, signature_count: usize) -> Option<usize> {
+    let header = rlp::encode(&Rlp::Bytes(rlp::uint_to_bytes(SIGNED_TX_TAG))).len() +
+        rlp::encode(&Rlp::Bytes(rlp::uint_to_bytes(SIGNED_TX_VERSION))).len() +
+        rlp::encoded_len(signature_count * rlp::encoded_len(64));
+    let signed = |size: usize| rlp::encoded_len(header + rlp::encoded_len(size));
+    // the envelope never grows smaller around a bigger transaction, so
+    // taking off what it adds to signed_size itself can only undershoot
+    let mut size = (2 * signed_size).saturating_sub(signed(signed_size));
+    while signed(size) < signed_size {
+        size += 1;
+    }
+    if signed(size) == signed_size {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+     * Signed transactions with their hashes, worked out from the
+     * node's serialization rules separately from this code.
+     */
+    const SPEND_TX: &str = "tx_+KgLAfhCuEAAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSYnKCkqKywtLi8wMTIzNDU2Nzg5Ojs8PT4/uGD4XgwBoQEAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eH6EBICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj+IiscjBInoAACGEjCc5UAAgAGFaGVsbG9XiGkv";
+    const SPEND_HASH: &str = "th_2mQ4xzQUjyomFfSFDnqBxTmuGELUb8qxwHET7pvAMecDWTvnsR";
+    const EXTEND_TX: &str = "tx_+HsLAfhCuEAAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSYnKCkqKywtLi8wMTIzNDU2Nzg5Ojs8PT4/tPMZAaEEZGVmZ2hpamtsbW5vcHFyc3R1dnd4eXp7fH1+f4CBgoMDgIIB9IYSMJzlQACCA+i+tQLt";
+    const EXTEND_HASH: &str = "th_2opXquG3vKoRVMnhChSbTXg3bn1tud5NeMtyCCnvuqVDPzTVmK";
+    const SIGNATURE: &str = "sg_12jVjGbD3wrDT6L19fyB486MyMfMdNjc148QTEgR2qypJqKtTHnBDUjubAxFytva52tzNzog1PChUSJ1vFMgt11fwd15";
+
+    fn spend() -> Value {
+        serde_json::from_str(r#"{
+            "type": "SpendTx",
+            "version": 1,
+            "sender_id": "ak_16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYG",
+            "recipient_id": "ak_F9hnD6sLacskNWeRQqZDUZDaRa12QjSZGXwqSuEe6C5283v7T",
+            "amount": 10000000000000000000,
+            "fee": 20000000000000,
+            "ttl": 0,
+            "nonce": 1,
+            "payload": "ba_aGVsbG+Vlcnf"
+        }"#).unwrap()
+    }
+
+    fn extend() -> Value {
+        serde_json::from_str(r#"{
+            "type": "OracleExtendTx",
+            "version": 1,
+            "oracle_id": "ok_mDV3PumBhDK2vhZ7JyH2yAPsexi1hdej9twk2juDRdQHg8ipN",
+            "nonce": 3,
+            "oracle_ttl": {"type": "delta", "value": 500},
+            "fee": 20000000000000,
+            "ttl": 1000
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn serializes_as_the_node_does() {
+        for &(encoded, hash, ref tx, size) in [(SPEND_TX, SPEND_HASH, spend(), 170),
+                                               (EXTEND_TX, EXTEND_HASH, extend(), 125)].iter() {
+            let serialized = serialize_signed_tx(&vec!(String::from(SIGNATURE)), tx).unwrap();
+            assert_eq!(serialized, encoding::decode_as("tx", encoded).unwrap());
+            assert_eq!(tx_hash(&serialized), hash);
+            let decoded = decode_signed_tx(encoded).unwrap();
+            assert_eq!(decoded.hash.to_string(), hash);
+            assert_eq!(decoded.signatures, vec!(String::from(SIGNATURE)));
+            assert_eq!(&decoded.tx, tx);
+            assert_eq!(signed_tx_size(&decoded).unwrap(), size);
+        }
+    }
+
+    #[test]
+    fn finds_the_unsigned_size() {
+        assert_eq!(unsigned_tx_size(170, 1), Some(96));
+        assert_eq!(unsigned_tx_size(125, 1), Some(52));
+        for size in [0, 1, 55, 56, 200, 255, 256, 70000].iter() {
+            for count in 0..4 {
+                let signed = rlp::encoded_len(2 + rlp::encoded_len(count * 66) +
+                                              rlp::encoded_len(*size));
+                assert_eq!(unsigned_tx_size(signed, count), Some(*size));
+            }
+        }
+        assert_eq!(unsigned_tx_size(0, 1), None);
+    }
+
+    #[test]
+    fn checks_the_hash() {
+        let mut decoded = decode_signed_tx(SPEND_TX).unwrap();
+        decoded.tx["nonce"] = Value::from(2);
+        assert!(signed_tx_size(&decoded).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let not_signed = encoding::encode("tx", &rlp::encode(&serialize_tx(&spend()).unwrap()));
+        for x in [not_signed.as_str(), "tx_", "th_2mQ4xzQUjyomFfSFDnqBxTmuGELUb8qxwHET7pvAMecDWTvnsR",
+                  "tx_+KgLAfhCuEAAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSYn"].iter() {
+            assert!(decode_signed_tx(x).is_err(), "{} decoded", x);
+        }
+        assert!(serialize_tx(&serde_json::from_str(r#"{"type": "NoSuchTx"}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn converts_decimals() {
+        for x in ["0", "1", "255", "256", "10000000000000000000", "340282366920938463463374607431768211456"].iter() {
+            assert_eq!(bytes_to_decimal(&decimal_to_bytes(x).unwrap()), *x);
+        }
+        assert_eq!(decimal_to_bytes("256").unwrap(), vec!(1, 0));
+        assert!(decimal_to_bytes("-1").is_err());
+        assert!(decimal_to_bytes("").is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use diesel::sql_query;
+use diesel::sql_types::Text;
 
 use epoch;
 use epoch::Epoch;
+use fees::{FeeEstimate, MAX_GENERATIONS};
+use ids::{AccountId, KeyBlockHash, MicroBlockHash, ParticipantId, TxHash};
 use models::*;
 
 use diesel::pg::PgConnection;
@@ -9,27 +12,286 @@ use diesel::RunQueryDsl;
 use r2d2::Pool;
 use r2d2_diesel::ConnectionManager;
 use rocket;
-use rocket::response::Failure;
+use rocket::response::{status, Failure};
 use rocket::http::{Method, Status};
+use rocket::request::{self, FormItems, FromRequest, Request};
 use rocket::Outcome::{Success};
 use rocket::{Outcome, State};
 use rocket_contrib::Json;
 use rocket_cors;
 use rocket_cors::{AllowedHeaders, AllowedOrigins};
+use search;
+use search::SearchResult;
 use serde_json;
+use serialization;
+use std;
 use std::path::PathBuf;
 use std::sync::Arc;
+use supervisor::{Supervisor, WorkerStatus};
+use validation;
+use validation::Rejection;
 
 pub struct MiddlewareServer {
     pub epoch: Epoch,
     pub dest_url: String, // address to forward to
     pub port: u16,        // port to listen on
     pub connection: Arc<Pool<ConnectionManager<PgConnection>>>, // DB connection
+    pub supervisor: Arc<Supervisor>, // populate workers, if any
+    pub network_id: Option<String>, // if set, check signatures for this network
+    pub min_gas_price: i64, // the node's, below which it won't take a transaction
 }
 
-// SQL santitizing method to prevent injection attacks.
-fn sanitize(s: String) -> String {
-    s.replace("'", "\\'")
+/*
+ * limit, order and cursor from the query string of the reorg, proof
+ * of fraud and mempool lists, e.g. ?limit=50&order=asc&cursor=1234,
+ * as for the transaction lists. At most 1000 items are returned at a
+ * time, 100 if limit isn't given.
+ */
+impl<'a, 'r> FromRequest<'a, 'r> for ListPage {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<ListPage, ()> {
+        let mut page = ListPage { cursor: None, descending: None, limit: 100 };
+        let query = match request.uri().query() {
+            Some(x) => x,
+            None => return Success(page),
+        };
+        for (key, value) in FormItems::from(query) {
+            let value = value.as_str();
+            let parsed = match key.as_str() {
+                "limit" => value.parse().map(|x| page.limit = x).is_ok(),
+                "cursor" => value.parse().map(|x| page.cursor = Some(x)).is_ok(),
+                "order" => {
+                    page.descending = Some(value == "desc");
+                    value == "asc" || value == "desc"
+                },
+                _ => true,
+            };
+            if !parsed {
+                return Outcome::Failure((Status::BadRequest, ()));
+            }
+        }
+        if page.limit < 1 || page.limit > 1000 {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+        Success(page)
+    }
+}
+
+/*
+ * How many of the most recent generations to look at, from the query
+ * string, e.g. ?generations=50. 10 if it isn't given.
+ */
+pub struct Generations {
+    pub count: i64,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Generations {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Generations, ()> {
+        let mut generations = Generations { count: RECENT_GENERATIONS };
+        let query = match request.uri().query() {
+            Some(x) => x,
+            None => return Success(generations),
+        };
+        for (key, value) in FormItems::from(query) {
+            if key.as_str() == "generations" {
+                generations.count = match value.as_str().parse() {
+                    Ok(x) => x,
+                    Err(_) => return Outcome::Failure((Status::BadRequest, ())),
+                };
+            }
+        }
+        if generations.count < 1 || generations.count > MAX_GENERATIONS {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+        Success(generations)
+    }
+}
+
+/*
+ * Who is making a request, as far as we can tell.
+ */
+pub struct Client {
+    pub remote_addr: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Client {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Client, ()> {
+        let headers = request.headers();
+        Success(Client {
+            remote_addr: request.remote().map(|x| x.ip().to_string()),
+            forwarded_for: headers.get_one("X-Forwarded-For").map(String::from),
+            user_agent: headers.get_one("User-Agent").map(String::from),
+        })
+    }
+}
+
+/*
+ * sender, recipient and tx_type from the query string of the mempool
+ * endpoints, e.g. ?sender=ak_...&tx_type=SpendTx. Anything left out
+ * matches everything.
+ */
+pub struct MempoolFilter {
+    pub sender: Option<String>,
+    pub recipient: Option<String>,
+    pub tx_type: Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for MempoolFilter {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<MempoolFilter, ()> {
+        let mut filter = MempoolFilter { sender: None, recipient: None, tx_type: None };
+        let query = match request.uri().query() {
+            Some(x) => x,
+            None => return Success(filter),
+        };
+        for (key, value) in FormItems::from(query) {
+            let value = match value.url_decode() {
+                Ok(x) => Some(x),
+                Err(_) => return Outcome::Failure((Status::BadRequest, ())),
+            };
+            match key.as_str() {
+                "sender" => filter.sender = value,
+                "recipient" => filter.recipient = value,
+                "tx_type" => filter.tx_type = value,
+                _ => (),
+            }
+        }
+        Success(filter)
+    }
+}
+
+/*
+ * role from the query string of the account endpoint, e.g.
+ * ?role=recipient. Left out, it matches every role.
+ */
+pub struct RoleFilter {
+    pub role: Option<String>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RoleFilter {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<RoleFilter, ()> {
+        let mut filter = RoleFilter { role: None };
+        let query = match request.uri().query() {
+            Some(x) => x,
+            None => return Success(filter),
+        };
+        for (key, value) in FormItems::from(query) {
+            if key.as_str() == "role" {
+                match value.url_decode() {
+                    Ok(x) => filter.role = Some(x),
+                    Err(_) => return Outcome::Failure((Status::BadRequest, ())),
+                };
+            }
+        }
+        Success(filter)
+    }
+}
+
+/*
+ * The query string of the transaction list endpoints, e.g.
+ * ?limit=50&order=desc&tx_type=SpendTx&from_height=1000&valid=true.
+ * cursor is the next of the page before. At most 1000 transactions
+ * are returned at a time, 100 if limit isn't given.
+ */
+impl<'a, 'r> FromRequest<'a, 'r> for TransactionFilter {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<TransactionFilter, ()> {
+        let mut filter = TransactionFilter {
+            tx_type: None,
+            from_height: None,
+            to_height: None,
+            from_time: None,
+            to_time: None,
+            valid: None,
+            descending: false,
+            cursor: None,
+            limit: 100,
+        };
+        let query = match request.uri().query() {
+            Some(x) => x,
+            None => return Success(filter),
+        };
+        for (key, value) in FormItems::from(query) {
+            let value = match value.url_decode() {
+                Ok(x) => x,
+                Err(_) => return Outcome::Failure((Status::BadRequest, ())),
+            };
+            let parsed = match key.as_str() {
+                "tx_type" => {
+                    filter.tx_type = Some(value);
+                    true
+                },
+                "from_height" => value.parse().map(|x| filter.from_height = Some(x)).is_ok(),
+                "to_height" => value.parse().map(|x| filter.to_height = Some(x)).is_ok(),
+                "from_time" => parse_millis(&value).map(|x| filter.from_time = Some(x)).is_some(),
+                "to_time" => parse_millis(&value).map(|x| filter.to_time = Some(x)).is_some(),
+                "valid" => value.parse().map(|x| filter.valid = Some(x)).is_ok(),
+                "limit" => value.parse().map(|x| filter.limit = x).is_ok(),
+                "cursor" => value.parse().map(|x| filter.cursor = Some(x)).is_ok(),
+                "order" => {
+                    filter.descending = value == "desc";
+                    value == "asc" || value == "desc"
+                },
+                _ => true,
+            };
+            if !parsed {
+                return Outcome::Failure((Status::BadRequest, ()));
+            }
+        }
+        if filter.limit < 1 || filter.limit > 1000 {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+        Success(filter)
+    }
+}
+
+fn parse_millis(value: &str) -> Option<i64> {
+    match value.parse() {
+        Ok(x) if is_valid_millis(x) => Some(x),
+        _ => None,
+    }
+}
+
+/*
+ * A 400, saying what was wrong with the request.
+ */
+fn bad_request(reason: &str) -> status::Custom<Json> {
+    let mut body = serde_json::Map::new();
+    body.insert(String::from("reason"), serde_json::Value::String(String::from(reason)));
+    status::Custom(Status::BadRequest, Json(serde_json::Value::Object(body)))
+}
+
+/*
+ * A 400 if a hash or id in the path isn't valid.
+ */
+fn valid<T>(param: Result<T, Box<std::error::Error>>) -> Result<T, status::Custom<Json>> {
+    param.map_err(|e| bad_request(&format!("{}", e)))
+}
+
+/*
+ * A 400 if a time in the path can't be a timestamp.
+ */
+fn valid_times(from: i64, to: i64) -> Result<(), status::Custom<Json>> {
+    if is_valid_millis(from) && is_valid_millis(to) {
+        Ok(())
+    } else {
+        Err(bad_request("Times must be from 1970 to the end of 9999"))
+    }
+}
+
+fn rejected(rejection: Rejection) -> status::Custom<Json> {
+    status::Custom(Status::BadRequest, Json(serde_json::to_value(&rejection).unwrap()))
 }
 
 /*
@@ -56,11 +318,30 @@ fn epoch_test_handler(state: State<MiddlewareServer>, path: PathBuf) -> Json<ser
 }
 
 /*
- * POST handler for Epoch
+ * POST handler for Epoch. Transactions are checked before they are
+ * passed on, and turned away with a 400 saying why if they fail.
  */
 #[post("/<path..>", format = "application/json", data = "<body>")]
-fn epoch_post_handler(state: State<MiddlewareServer>, path: PathBuf, body: String) -> Json {
+fn epoch_post_handler(state: State<MiddlewareServer>, path: PathBuf, body: String,
+                      client: Client) ->
+    Result<Json, status::Custom<Json>> {
     debug!("{}", body);
+    let submitting = path.to_str() == Some("transactions");
+    if submitting {
+        let encoded = match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(ref x) if x["tx"].is_string() => String::from(x["tx"].as_str().unwrap()),
+            _ => return Err(rejected(Rejection {
+                check: String::from("decode"),
+                reason: String::from("Body should be {\"tx\": \"tx_...\"}"),
+            })),
+        };
+        let conn = state.epoch.get_connection().unwrap();
+        if let Err(rejection) = validation::check_signed_tx(&conn, &encoded, &state.network_id,
+                                                          state.min_gas_price) {
+            info!("Rejected transaction: {:?}", rejection);
+            return Err(rejected(rejection));
+        }
+    }
     let response = state
         .epoch
         .post_naked(
@@ -70,7 +351,21 @@ fn epoch_post_handler(state: State<MiddlewareServer>, path: PathBuf, body: Strin
         )
         .unwrap();
     debug!("Response: {}", response);
-    Json(serde_json::from_str(response.as_str()).unwrap())
+    let response: serde_json::Value = serde_json::from_str(response.as_str()).unwrap();
+    if let (true, Some(hash)) = (submitting, response["tx_hash"].as_str()) {
+        let submission = InsertableSubmission {
+            hash: String::from(hash),
+            remote_addr: client.remote_addr,
+            forwarded_for: client.forwarded_for,
+            user_agent: client.user_agent,
+            submitted_at: std::time::SystemTime::now(),
+            status: String::from("pending"),
+        };
+        if let Err(e) = submission.save(&state.epoch.get_connection().unwrap()) {
+            error!("Failed to record submission of {}: {:?}", hash, e);
+        }
+    }
+    Ok(Json(response))
 }
 
 /*
@@ -116,7 +411,10 @@ fn key_block_at_height(state: State<MiddlewareServer>, height: i64) -> Json {
 }
 
 #[get("/transactions/<hash>")]
-fn transaction_at_hash(state: State<MiddlewareServer>, hash: String) -> Json {
+fn transaction_at_hash(state: State<MiddlewareServer>, hash: Result<TxHash, Box<std::error::Error>>) ->
+    Result<Json, status::Custom<Json>>
+{
+    let hash = valid(hash)?;
     let conn = state.epoch.get_connection().unwrap();
     let tx: Transaction = match Transaction::load_at_hash(&conn, &hash) {
         Some(x) => x,
@@ -124,15 +422,27 @@ fn transaction_at_hash(state: State<MiddlewareServer>, hash: String) -> Json {
             info!("Transaction not found at hash {}", &hash);
             let mut path = std::path::PathBuf::new();
             path.push(format!("/transactions/hash/{}", hash));
-            return epoch_get_handler(state, path);
+            return Ok(epoch_get_handler(state, path));
         },
     };
-    Json(serde_json::from_str(&serde_json::to_string(
-        &JsonTransaction::from_transaction(&tx)).unwrap()).unwrap())
+    let lifecycle = match TransactionEvent::load_for_hash(&conn, &hash) {
+        Ok(x) => x.iter().map(JsonTransactionEvent::from_transaction_event).collect(),
+        Err(e) => {
+            error!("Error loading events for transaction {}: {:?}", &hash, e);
+            vec!()
+        },
+    };
+    Ok(Json(serde_json::to_value(&JsonTransactionWithLifecycle {
+        transaction: JsonTransaction::from_transaction(&tx),
+        lifecycle,
+    }).unwrap()))
 }
 
 #[get("/key-blocks/hash/<hash>", rank=1)]
-fn key_block_at_hash(state: State<MiddlewareServer>, hash: String) -> Json {
+fn key_block_at_hash(state: State<MiddlewareServer>, hash: Result<KeyBlockHash, Box<std::error::Error>>) ->
+    Result<Json, status::Custom<Json>>
+{
+    let hash = valid(hash)?;
     let conn = state.epoch.get_connection().unwrap();
     let key_block = match KeyBlock::load_at_hash(&conn, &hash) {
         Some(x) => x,
@@ -140,20 +450,23 @@ fn key_block_at_hash(state: State<MiddlewareServer>, hash: String) -> Json {
             info!("Key block not found at hash {}", &hash);
             let mut path = std::path::PathBuf::new();
             path.push(format!("/key-blocks/hash/{}", hash));
-            return epoch_get_handler(state, path);
+            return Ok(epoch_get_handler(state, path));
         }
     };
     info!("Serving key block {} from DB", hash);
-    Json(serde_json::from_str(&serde_json::to_string(
-        &JsonKeyBlock::from_key_block(&key_block)).unwrap()).unwrap())
+    Ok(Json(serde_json::from_str(&serde_json::to_string(
+        &JsonKeyBlock::from_key_block(&key_block)).unwrap()).unwrap()))
 }
 
 #[get("/micro-blocks/hash/<hash>/transactions", rank=1)]
 fn transactions_in_micro_block_at_hash(state: State<MiddlewareServer>,
-                                       hash: String) -> 
-    Json<JsonTransactionList> {
-        let sql = format!("select t.* from transactions t, micro_blocks m where t.micro_block_id = m.id and m.hash = '{}'", sanitize(hash));
-        let transactions: Vec<Transaction> = sql_query(sql).load(&*state.connection.get().unwrap()).unwrap();
+                                       hash: Result<MicroBlockHash, Box<std::error::Error>>) -> 
+    Result<Json<JsonTransactionList>, status::Custom<Json>> {
+        let hash = valid(hash)?;
+        let sql = "select t.* from transactions t, micro_blocks m where t.micro_block_id = m.id and m.hash = $1 order by t.transaction_index";
+        let transactions: Vec<Transaction> = sql_query(sql)
+            .bind::<Text, _>(&hash)
+            .load(&*state.connection.get().unwrap()).unwrap();
         let mut trans: Vec<JsonTransaction> = vec!();
         for i in 0 .. transactions.len() {
             trans.push(JsonTransaction::from_transaction(&transactions[i]));
@@ -161,79 +474,263 @@ fn transactions_in_micro_block_at_hash(state: State<MiddlewareServer>,
         let list = JsonTransactionList {
             transactions: trans,
         };
-        Json(list)
+        Ok(Json(list))
     }
 
 
 /*
- * Gets all transactions for an account
+ * Gets the transactions an account, contract, oracle, name or channel
+ * takes part in, or with ?role=, only those where it has that role
  */
 #[get("/transactions/account/<account>")]
-fn transactions_for_account(state: State<MiddlewareServer>, account: String) ->
-    Json<JsonTransactionList> {
-    let sql = format!("select * from transactions where tx->>'sender_id'='{}' order by id asc", sanitize(account));
-    let transactions: Vec<Transaction> = sql_query(sql).load(&*state.connection.get().unwrap()).unwrap();
-    let mut trans: Vec<JsonTransaction> = vec!();
-    for i in 0 .. transactions.len() {
-        trans.push(JsonTransaction::from_transaction(&transactions[i]));
+fn transactions_for_account(state: State<MiddlewareServer>,
+                            account: Result<ParticipantId, Box<std::error::Error>>,
+                            role: RoleFilter, filter: TransactionFilter) ->
+    Result<Json<JsonTransactionPage>, status::Custom<Json>>
+{
+    let account = valid(account)?;
+    let transactions = Transaction::load_list(
+        &*state.connection.get().unwrap(), &Some(account.to_string()), &role.role, &filter).unwrap();
+    Ok(Json(JsonTransactionPage::from_transactions(&transactions, filter.limit)))
+}
+
+/*
+ * Gets the blocks a transaction was in before they were lost to a
+ * fork, and where it is now
+ */
+#[get("/orphans/transactions/<hash>")]
+fn orphans_for_transaction(state: State<MiddlewareServer>, hash: Result<TxHash, Box<std::error::Error>>) ->
+    Result<Json<JsonTransactionOrphans>, status::Custom<Json>> {
+    let hash = valid(hash)?;
+    let conn = state.epoch.get_connection().unwrap();
+    let orphans = OrphanedTransaction::load_for_hash(&conn, &hash).unwrap();
+    let mut orphaned: Vec<JsonOrphanedTransaction> = vec!();
+    for i in 0 .. orphans.len() {
+        orphaned.push(JsonOrphanedTransaction::from_orphaned_transaction(&orphans[i]));
     }
-    let list = JsonTransactionList {
-        transactions: trans,
+    let current = match Transaction::load_at_hash(&conn, &hash) {
+        Some(x) => Some(JsonTransaction::from_transaction(&x)),
+        None => None,
     };
-    Json(list)
+    Ok(Json(JsonTransactionOrphans { orphaned, current }))
 }
 
 /*
  * Gets transactions between blocks
  */
 #[get("/transactions/interval/<from>/<to>")]
-fn transactions_for_interval(state: State<MiddlewareServer>, from: i64, to: i64) ->
-    Json<JsonTransactionList> {
-    let sql = format!("select t.* from transactions t, micro_blocks m, key_blocks k where t.micro_block_id=m.id and m.key_block_id=k.id and k.height >={} and k.height <= {} order by k.height asc", from, to);
-    let transactions: Vec<Transaction> = sql_query(sql).load(&*state.connection.get().unwrap()).unwrap();
-    let mut trans: Vec<JsonTransaction> = vec!();
-    for i in 0 .. transactions.len() {
-        trans.push(JsonTransaction::from_transaction(&transactions[i]));
+fn transactions_for_interval(state: State<MiddlewareServer>, from: i64, to: i64,
+                             filter: TransactionFilter) -> Json<JsonTransactionPage> {
+    let filter = TransactionFilter { from_height: Some(from), to_height: Some(to), ..filter };
+    let transactions = Transaction::load_list(
+        &*state.connection.get().unwrap(), &None, &None, &filter).unwrap();
+    Json(JsonTransactionPage::from_transactions(&transactions, filter.limit))
+}
+
+/*
+ * Gets the transactions an account, contract, oracle, name or channel
+ * takes part in which were mined between two times in milliseconds
+ */
+#[get("/transactions/account/<account>/time/<from>/<to>")]
+fn transactions_for_account_between_times(state: State<MiddlewareServer>,
+                                          account: Result<ParticipantId, Box<std::error::Error>>,
+                                          from: i64, to: i64, role: RoleFilter,
+                                          filter: TransactionFilter) ->
+    Result<Json<JsonTransactionPage>, status::Custom<Json>>
+{
+    let account = valid(account)?;
+    valid_times(from, to)?;
+    let conn = state.connection.get().unwrap();
+    let filter = filter.between_times(&*conn, from, to);
+    let transactions = Transaction::load_list(
+        &*conn, &Some(account.to_string()), &role.role, &filter).unwrap();
+    Ok(Json(JsonTransactionPage::from_transactions(&transactions, filter.limit)))
+}
+
+/*
+ * Gets transactions mined between two times in milliseconds
+ */
+#[get("/transactions/interval/time/<from>/<to>")]
+fn transactions_between_times(state: State<MiddlewareServer>, from: i64, to: i64,
+                              filter: TransactionFilter) ->
+    Result<Json<JsonTransactionPage>, status::Custom<Json>>
+{
+    valid_times(from, to)?;
+    let conn = state.connection.get().unwrap();
+    let filter = filter.between_times(&*conn, from, to);
+    let transactions = Transaction::load_list(&*conn, &None, &None, &filter).unwrap();
+    Ok(Json(JsonTransactionPage::from_transactions(&transactions, filter.limit)))
+}
+
+/*
+ * Gets the most recent key block at or before a time in milliseconds
+ */
+#[get("/key-blocks/at-time/<millis>")]
+fn key_block_at_time(state: State<MiddlewareServer>, millis: i64) -> Option<Json<JsonKeyBlock>> {
+    let conn = state.connection.get().unwrap();
+    let key_block = KeyBlock::load_at_time(&*conn, millis)?;
+    Some(Json(JsonKeyBlock::from_key_block(&key_block)))
+}
+
+/*
+ * Gets the reorgs we have detected, most recent first
+ */
+#[get("/reorgs")]
+fn reorgs(state: State<MiddlewareServer>, page: ListPage) -> Json<JsonReorgPage> {
+    let conn = state.epoch.get_connection().unwrap();
+    let _reorgs = Reorg::load_page(&conn, &page).unwrap();
+    Json(JsonReorgPage::from_reorgs(&_reorgs, &page))
+}
+
+/*
+ * Gets the proofs of fraud included in micro blocks, highest first
+ */
+#[get("/proofs-of-fraud")]
+fn proofs_of_fraud(state: State<MiddlewareServer>, page: ListPage) -> Json<JsonProofOfFraudPage> {
+    let conn = state.epoch.get_connection().unwrap();
+    let proofs = ProofOfFraud::load_page(&conn, &page).unwrap();
+    Json(JsonProofOfFraudPage::from_proofs_of_fraud(&proofs, &page))
+}
+
+/*
+ * Gets the transactions waiting in the mempool, longest waiting first
+ */
+#[get("/mempool")]
+fn mempool(state: State<MiddlewareServer>, filter: MempoolFilter, page: ListPage) ->
+    Json<JsonPendingTransactionPage> {
+    let conn = state.epoch.get_connection().unwrap();
+    let pending = PendingTransaction::load_page(
+        &conn, &filter.sender, &filter.recipient, &filter.tx_type, &page).unwrap();
+    Json(JsonPendingTransactionPage::from_pending_transactions(&pending, &page))
+}
+
+/*
+ * Gets the transactions waiting in the mempool which an account sent
+ * or will receive, longest waiting first
+ */
+#[get("/mempool/account/<account>")]
+fn mempool_for_account(state: State<MiddlewareServer>,
+                       account: Result<AccountId, Box<std::error::Error>>, page: ListPage) ->
+    Result<Json<JsonPendingTransactionPage>, status::Custom<Json>> {
+    let account = valid(account)?;
+    let conn = state.epoch.get_connection().unwrap();
+    let pending = PendingTransaction::load_for_account(&conn, &account, &page).unwrap();
+    Ok(Json(JsonPendingTransactionPage::from_pending_transactions(&pending, &page)))
+}
+
+/*
+ * Decodes a signed transaction, {"tx": "tx_..."}, into the JSON the
+ * node would give for it
+ */
+#[post("/decode-tx", format = "application/json", data = "<body>")]
+fn decode_tx(body: String) -> Result<Json<JsonTransaction>, status::Custom<Json>> {
+    let encoded = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(x) => match x["tx"].as_str() {
+            Some(tx) => String::from(tx),
+            None => return Err(bad_request("Body should be {\"tx\": \"tx_...\"}")),
+        },
+        Err(e) => return Err(bad_request(&format!("Body isn't JSON: {}", e))),
+    };
+    match serialization::decode_signed_tx(&encoded) {
+        Ok(x) => Ok(Json(x)),
+        Err(e) => Err(bad_request(&format!("{}", e))),
     }
-    let list = JsonTransactionList {
-        transactions: trans,
+}
+
+/*
+ * Gets the account's pending transactions which are stuck, either
+ * behind a missing nonce or because they pay too little
+ */
+#[get("/mempool/account/<account>/stuck")]
+fn stuck_transactions_for_account(state: State<MiddlewareServer>,
+                                  account: Result<AccountId, Box<std::error::Error>>) ->
+    Result<Json<JsonStuckTransactions>, status::Custom<Json>> {
+    let account = valid(account)?;
+    let conn = state.epoch.get_connection().unwrap();
+    Ok(Json(JsonStuckTransactions::for_account(&conn, &account).unwrap()))
+}
+
+/*
+ * Gets percentiles of how long transactions mined between two heights
+ * took to be included, by type and fee
+ */
+#[get("/inclusion-latency/height/<from>/<to>")]
+fn inclusion_latency_for_heights(state: State<MiddlewareServer>, from: i64, to: i64) ->
+    Json<Vec<InclusionLatency>> {
+    let conn = state.epoch.get_connection().unwrap();
+    Json(InclusionLatency::for_heights(&conn, from, to).unwrap())
+}
+
+/*
+ * The same, for transactions mined between two times, in milliseconds
+ */
+#[get("/inclusion-latency/time/<from>/<to>")]
+fn inclusion_latency_for_times(state: State<MiddlewareServer>, from: i64, to: i64) ->
+    Json<Vec<InclusionLatency>> {
+    let conn = state.epoch.get_connection().unwrap();
+    Json(InclusionLatency::for_times(&conn, from, to).unwrap())
+}
+
+/*
+ * Gets what has happened to a transaction posted through the middleware
+ */
+#[get("/submissions/<hash>")]
+fn submission(state: State<MiddlewareServer>, hash: Result<TxHash, Box<std::error::Error>>) ->
+    Result<Option<Json<JsonSubmission>>, status::Custom<Json>> {
+    let hash = valid(hash)?;
+    let conn = state.epoch.get_connection().unwrap();
+    let found = match Submission::load_for_hash(&conn, &hash).unwrap() {
+        Some(x) => x,
+        None => return Ok(None),
     };
-    Json(list)
+    let top_height = KeyBlock::top_height(&conn).unwrap();
+    Ok(Some(Json(JsonSubmission::from_submission(&found, top_height))))
 }
 
 /*
- * Gets average gas price for a block
+ * Finds what a height, hash, id or name refers to
  */
-#[get("/key-blocks/height/<height>/gas-price")]
-fn key_block_gas_price(state: State<MiddlewareServer>, height: i64) -> Option<String> {
-    let sql = format!("\
-select t.* from transactions t, micro_blocks m, key_blocks k where \
-t.micro_block_id=m.id and \
-m.key_block_id=k.id and \
-k.height = {} and \
-t.tx_type in ('SpendTx')", height);
-    println!("{}", sql);
-    let transactions: Vec<Transaction> = sql_query(sql).load(&*state.connection.get().unwrap()).unwrap();
-    let mut fees: i64 = 0;
-    let mut sizes: i64 = 0;
-    for i in 0 .. transactions.len() {
-        fees += transactions[i].fee;
-        sizes += transactions[i].size as i64;
-    }
-    if sizes == 0 {
-        return None;
+#[get("/search/<query>")]
+fn search_for(state: State<MiddlewareServer>, query: String) ->
+    Result<Option<Json<SearchResult>>, status::Custom<Json>>
+{
+    let conn = state.epoch.get_connection().unwrap();
+    match search::search(&conn, &state.epoch, &query) {
+        Ok(x) => Ok(x.map(Json)),
+        Err(e) => Err(bad_request(&format!("{}", e))),
     }
-    Some(format!("{}", fees/sizes as i64))
+}
+
+/*
+ * Reports the state of the populate workers running in this process
+ */
+#[get("/status")]
+fn status(state: State<MiddlewareServer>) -> Json<Vec<WorkerStatus>> {
+    Json(state.supervisor.statuses())
+}
+
+/*
+ * Gets what transactions have paid in fees over recent generations,
+ * and the rules for the minimum fee
+ */
+#[get("/fee-estimate")]
+fn fee_estimate(state: State<MiddlewareServer>, window: Generations) -> Json<FeeEstimate> {
+    let conn = state.epoch.get_connection().unwrap();
+    Json(FeeEstimate::for_recent_generations(&conn, window.count, state.min_gas_price).unwrap())
 }
 
 
 impl MiddlewareServer {
     pub fn start(self) {
+        self.rocket().launch();
+    }
+
+    pub fn rocket(self) -> rocket::Rocket {
         let allowed_origins = AllowedOrigins::all();
         let options = rocket_cors::Cors {
             allowed_origins,
-            allowed_methods: vec![Method::Get].into_iter().map(From::from).collect(),
-            allowed_headers: AllowedHeaders::some(&["Authorization", "Accept"]),
+            allowed_methods: vec![Method::Get, Method::Post].into_iter().map(From::from).collect(),
+            allowed_headers: AllowedHeaders::some(&["Authorization", "Accept", "Content-Type"]),
             allow_credentials: true,
             ..Default::default()
         };
@@ -241,7 +738,22 @@ impl MiddlewareServer {
         rocket::ignite()
             .mount("/middleware", routes![transactions_for_account])
             .mount("/middleware", routes![transactions_for_interval])
-            .mount("/middleware", routes![key_block_gas_price])
+            .mount("/middleware", routes![transactions_for_account_between_times])
+            .mount("/middleware", routes![transactions_between_times])
+            .mount("/middleware", routes![key_block_at_time])
+            .mount("/middleware", routes![fee_estimate])
+            .mount("/middleware", routes![decode_tx])
+            .mount("/middleware", routes![submission])
+            .mount("/middleware", routes![orphans_for_transaction])
+            .mount("/middleware", routes![reorgs])
+            .mount("/middleware", routes![proofs_of_fraud])
+            .mount("/middleware", routes![mempool])
+            .mount("/middleware", routes![mempool_for_account])
+            .mount("/middleware", routes![stuck_transactions_for_account])
+            .mount("/middleware", routes![inclusion_latency_for_heights])
+            .mount("/middleware", routes![inclusion_latency_for_times])
+            .mount("/middleware", routes![search_for])
+            .mount("/middleware", routes![status])
             .mount("/v2", routes![epoch_get_handler])
             .mount("/v2", routes![epoch_post_handler])
             .mount("/api", routes![epoch_api_handler])
@@ -252,6 +764,73 @@ impl MiddlewareServer {
             .mount("/v2", routes![transactions_in_micro_block_at_hash])
             .attach(options)
             .manage(self)
-            .launch();
+    }
+}
+
+/*
+ * These need a migrated DB at DATABASE_URL, so run them with
+ * cargo test -- --ignored
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::Client;
+
+    // things which would have got into SQL built with format!
+    const HOSTILE: [&str; 7] = [
+        "ak_%27%3B%20DROP%20TABLE%20transactions%3B%20--",
+        "mh_%27%20OR%20%271%27%3D%271",
+        "ak_%5C%27%3B%20SELECT%20pg_sleep%2810%29%3B%20--",
+        "%27%29%3B%20DELETE%20FROM%20key_blocks%3B%20--",
+        "%E2%80%99%20OR%201%3D1",
+        "a%C3%A9b",
+        "ak_%00",
+    ];
+
+    fn client() -> Client {
+        let url = String::from("http://localhost:3013");
+        let server = MiddlewareServer {
+            epoch: Epoch::new(url.clone(), 1),
+            dest_url: url,
+            port: 3013,
+            connection: epoch::establish_connection(1),
+            supervisor: Arc::new(Supervisor::new()),
+            network_id: None,
+            min_gas_price: ::fees::MINIMUM_GAS_PRICE,
+        };
+        Client::new(server.rocket()).unwrap()
+    }
+
+    fn get(client: &Client, path: &str) -> Status {
+        client.get(path).dispatch().status()
+    }
+
+    #[test]
+    #[ignore]
+    fn hostile_accounts_are_rejected() {
+        let client = client();
+        for x in HOSTILE.iter() {
+            let path = format!("/middleware/transactions/account/{}", x);
+            assert_eq!(get(&client, &path), Status::BadRequest, "{}", path);
+        }
+        // still there
+        let path = "/middleware/transactions/account/\
+                    ak_16qJFWMMHFy3xDdLmvUeyc2S6FrWRhJP51HsvDYdz9d1FsYG";
+        assert_eq!(get(&client, path), Status::Ok);
+    }
+
+    #[test]
+    #[ignore]
+    fn hostile_micro_block_hashes_are_rejected() {
+        let client = client();
+        for x in HOSTILE.iter() {
+            let path = format!("/v2/micro-blocks/hash/{}/transactions", x);
+            assert_eq!(get(&client, &path), Status::BadRequest, "{}", path);
+        }
+        let mut hash = [0u8; 32];
+        hash[0] = 1;
+        let path = format!("/v2/micro-blocks/hash/{}/transactions",
+                           ::encoding::encode("mh", &hash));
+        assert_eq!(get(&client, &path), Status::Ok);
     }
 }
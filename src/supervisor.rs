@@ -0,0 +1,178 @@
+use std;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/*
+ * Set once, when we are asked to stop. Workers check it between units
+ * of work, and sleep through it rather than through thread::sleep so
+ * that they notice promptly.
+ */
+#[derive(Clone)]
+pub struct Shutdown {
+    flag: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown { flag: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn set(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /*
+     * Sleep for up to this long. Returns false if we are shutting
+     * down, so loops can be written `while shutdown.sleep(...)`.
+     */
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let start = Instant::now();
+        while !self.is_set() && start.elapsed() < duration {
+            thread::sleep(Duration::from_millis(200));
+        }
+        !self.is_set()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum WorkerState {
+    Running,
+    Restarting,
+    Finished,
+    Stopped,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorkerStatus {
+    pub name: String,
+    pub state: WorkerState,
+    pub restarts: u32,
+    pub last_panic: Option<String>,
+}
+
+/*
+ * Runs the populate workers in their own threads. A worker which
+ * panics is logged and started again, waiting 1 second after the
+ * first crash and doubling up to a minute if it keeps crashing. A
+ * worker which returns is finished. On shutdown the workers are left
+ * to finish what they are doing, and join() waits for them.
+ */
+pub struct Supervisor {
+    pub shutdown: Shutdown,
+    statuses: Arc<Mutex<BTreeMap<String, WorkerStatus>>>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Supervisor {
+        Supervisor {
+            shutdown: Shutdown::new(),
+            statuses: Arc::new(Mutex::new(BTreeMap::new())),
+            handles: Mutex::new(vec!()),
+        }
+    }
+
+    pub fn spawn<F>(&self, name: &str, f: F)
+    where
+        F: Fn(&Shutdown) + Send + 'static,
+    {
+        let name = String::from(name);
+        let shutdown = self.shutdown.clone();
+        let statuses = self.statuses.clone();
+        set_status(&statuses, &name, WorkerState::Running, None);
+        let handle = thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || {
+                let mut crashes: u32 = 0;
+                loop {
+                    let started = Instant::now();
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&shutdown)));
+                    if shutdown.is_set() {
+                        set_status(&statuses, &name, WorkerState::Stopped, None);
+                        info!("Worker {} stopped", name);
+                        return;
+                    }
+                    let payload = match result {
+                        Ok(_) => {
+                            set_status(&statuses, &name, WorkerState::Finished, None);
+                            debug!("Worker {} finished", name);
+                            return;
+                        },
+                        Err(payload) => panic_message(&payload),
+                    };
+                    if started.elapsed() > Duration::from_secs(60) {
+                        crashes = 0;
+                    }
+                    let backoff = std::cmp::min(1u64 << std::cmp::min(crashes, 6), 60);
+                    crashes += 1;
+                    error!("Worker {} panicked: {}. Restarting in {}s", name, payload, backoff);
+                    set_status(&statuses, &name, WorkerState::Restarting, Some(payload));
+                    if !shutdown.sleep(Duration::from_secs(backoff)) {
+                        set_status(&statuses, &name, WorkerState::Stopped, None);
+                        return;
+                    }
+                    set_status(&statuses, &name, WorkerState::Running, None);
+                }
+            })
+            .expect("Failed to spawn worker thread");
+        self.handles.lock().unwrap().push(handle);
+    }
+
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        self.statuses.lock().unwrap().values().cloned().collect()
+    }
+
+    /*
+     * Block until shutdown is requested, then wait for every worker
+     * to stop.
+     */
+    pub fn run_until_shutdown(&self) {
+        while self.shutdown.sleep(Duration::from_secs(40)) {
+            for status in self.statuses() {
+                debug!("Worker {}: {:?}, {} restarts", status.name, status.state, status.restarts);
+            }
+        }
+        info!("Shutting down, waiting for workers to finish");
+        let handles: Vec<JoinHandle<()>> = self.handles.lock().unwrap().drain(..).collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+        info!("All workers stopped");
+    }
+}
+
+fn set_status(statuses: &Mutex<BTreeMap<String, WorkerStatus>>, name: &String,
+              state: WorkerState, panic: Option<String>) {
+    let mut statuses = statuses.lock().unwrap();
+    let status = statuses.entry(name.clone()).or_insert(WorkerStatus {
+        name: name.clone(),
+        state,
+        restarts: 0,
+        last_panic: None,
+    });
+    status.state = state;
+    if panic.is_some() {
+        status.restarts += 1;
+        status.last_panic = panic;
+    }
+}
+
+fn panic_message(payload: &Box<Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        return String::from(*s);
+    }
+    if let Some(s) = payload.downcast_ref::<String>() {
+        return s.clone();
+    }
+    String::from("unknown panic")
+}
@@ -0,0 +1,171 @@
+use crypto::ed25519;
+use diesel::pg::PgConnection;
+use serde_json::Value;
+use std;
+
+use encoding;
+use fees;
+use models::{sender_id, AccountNonce};
+use rlp;
+use rlp::Rlp;
+use serialization;
+
+/*
+ * Why we won't pass a transaction on to the node. check is which of
+ * the checks below failed: "decode", "signature", "nonce" or "fee".
+ */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Rejection {
+    pub check: String,
+    pub reason: String,
+}
+
+fn reject<T>(check: &str, reason: String) -> Result<T, Rejection> {
+    Err(Rejection {
+        check: String::from(check),
+        reason,
+    })
+}
+
+/*
+ * Look at a "tx_..." signed transaction before it is posted to the
+ * node, and turn it away if
+ *
+ * - it can't be decoded
+ * - network_id is given and the sender hasn't signed it for that network
+ * - the sender has already mined a transaction with its nonce
+ * - its fee doesn't pay for its gas at min_gas_price
+ *
+ * Nonces are checked against the DB, so a transaction may get past
+ * that check if we are behind the chain, but the node will catch it.
+ */
+pub fn check_signed_tx(conn: &PgConnection, encoded: &str, network_id: &Option<String>,
+                       min_gas_price: i64) -> Result<(), Rejection>
+{
+    let serialized = match encoding::decode_as("tx", encoded) {
+        Ok(x) => x,
+        Err(e) => return reject("decode", format!("{}", e)),
+    };
+    let signed = match serialization::decode_signed_tx(encoded) {
+        Ok(x) => x,
+        Err(e) => return reject("decode", format!("{}", e)),
+    };
+    let (signatures, inner) = match unsign(&serialized) {
+        Ok(x) => x,
+        Err(e) => return reject("decode", format!("{}", e)),
+    };
+    let tx = &signed.tx;
+    let sender = sender_id(tx);
+    if let Some(ref network_id) = *network_id {
+        let sender = match sender {
+            Some(ref x) => x,
+            None => return reject("signature", String::from("Transaction has no sender")),
+        };
+        if let Err(e) = check_signature(&signatures, &inner, sender, network_id) {
+            return reject("signature", format!("{}", e));
+        }
+    }
+    if let (Some(ref account), Some(nonce)) = (sender, tx["nonce"].as_i64()) {
+        let last = match AccountNonce::load(conn, account) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Couldn't load nonce of {}: {:?}", account, e);
+                0
+            },
+        };
+        if nonce <= last {
+            return reject("nonce", format!("Nonce {} has already been used by {}, whose last \
+                                            nonce was {}", nonce, account, last));
+        }
+    }
+    check_fee(tx, &inner, min_gas_price)
+}
+
+/*
+ * The node prices the transaction without its signatures, serialized
+ * as inner.
+ */
+fn check_fee(tx: &Value, inner: &[u8], min_gas_price: i64) -> Result<(), Rejection> {
+    let tx_type = tx["type"].as_str().unwrap_or("");
+    if ORACLE_TTL_TYPES.contains(&tx_type) {
+        return Ok(());
+    }
+    let minimum = fees::min_fee(tx_type, inner.len() as i64, min_gas_price);
+    match tx["fee"].as_i64() {
+        Some(fee) if fee >= minimum => Ok(()),
+        Some(fee) => reject("fee", format!("Fee {} is less than the minimum of {} for a {} of \
+                                            {} bytes", fee, minimum, tx_type, inner.len())),
+        None => reject("fee", String::from("Fee is too large")),
+    }
+}
+
+/*
+ * Oracle transactions also pay for the time they keep their oracle or
+ * query alive, which depends on the chain height, so we leave their fee
+ * for the node to check.
+ */
+const ORACLE_TTL_TYPES: [&str; 4] = [
+    "OracleRegisterTx",
+    "OracleExtendTx",
+    "OracleQueryTx",
+    "OracleResponseTx",
+];
+
+/*
+ * Split a serialized signed transaction into its signatures and the
+ * serialized transaction they sign.
+ */
+fn unsign(serialized: &[u8]) -> Result<(Vec<Rlp>, Vec<u8>), Box<std::error::Error>> {
+    let items = match rlp::decode(serialized)? {
+        Rlp::List(x) => x,
+        Rlp::Bytes(_) => return Err(From::from("Not a signed transaction")),
+    };
+    match (items.get(2), items.get(3)) {
+        (Some(&Rlp::List(ref s)), Some(&Rlp::Bytes(ref t))) => Ok((s.clone(), t.clone())),
+        _ => Err(From::from("Not a signed transaction")),
+    }
+}
+
+/*
+ * One of the signatures must be the sender's, over the network id
+ * followed by the serialized transaction.
+ */
+fn check_signature(signatures: &[Rlp], tx: &[u8], sender: &String, network_id: &String) ->
+    Result<(), Box<std::error::Error>>
+{
+    let public_key = encoding::decode_as("ak", sender)?;
+    let mut message = network_id.as_bytes().to_vec();
+    message.extend(tx);
+    for s in signatures {
+        if let Rlp::Bytes(ref signature) = *s {
+            if signature.len() == 64 && ed25519::verify(&message, &public_key, signature) {
+                return Ok(());
+            }
+        }
+    }
+    Err(From::from(format!("Not signed by {} for network {}", sender, network_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a SpendTx with one signature, from serialization's tests
+    const SPEND_TX: &str = "tx_+KgLAfhCuEAAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAhIiMkJSYnKCkqKywtLi8wMTIzNDU2Nzg5Ojs8PT4/uGD4XgwBoQEAAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eH6EBICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj+IiscjBInoAACGEjCc5UAAgAGFaGVsbG9XiGkv";
+
+    #[test]
+    fn prices_transactions_as_the_fee_estimate_does() {
+        let serialized = encoding::decode_as("tx", SPEND_TX).unwrap();
+        let (signatures, inner) = unsign(&serialized).unwrap();
+        let mut tx = serialization::decode_signed_tx(SPEND_TX).unwrap().tx;
+        // what the fee estimate works out from the size we store
+        let gas = fees::signed_tx_gas("SpendTx", serialized.len() as i64, signatures.len()).unwrap();
+        assert_eq!(gas, fees::gas("SpendTx", 96));
+        for &price in [fees::MINIMUM_GAS_PRICE, 1_000_000].iter() {
+            tx["fee"] = Value::from(gas * price);
+            assert!(check_fee(&tx, &inner, price).is_ok());
+            tx["fee"] = Value::from(gas * price - 1);
+            assert!(check_fee(&tx, &inner, price).is_err());
+        }
+    }
+}